@@ -1,16 +1,29 @@
 use std::borrow::Cow;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, IsTerminal};
 use std::path::Path;
 use std::rc::Rc;
 
-use anyhow::{bail, Ok, Result};
+use anyhow::{bail, Context, Ok, Result};
 use clap::Parser;
-use image::{DynamicImage, GenericImageView};
+use image::{DynamicImage, Frame, GenericImageView, ImageFormat};
 
-use rmr::carve::Carver;
-use rmr::change::Change;
-use rmr::config::Config;
-use rmr::debug::create_debug_image;
+use rmr::carve::{Carver, Direction, SearchMode, Strategy};
+use rmr::codec::{
+    check_animation_format, is_stdio, read_image, read_input, write_animation, write_image,
+    EncodeOptions, Input, STDIO,
+};
+use rmr::color::ColorSpace;
+use rmr::compact::CompactCarver;
+use rmr::config::{
+    get_layer_path, Command, Config, EnergyConfig, InspectConfig, OutputOptions, RemoveConfig,
+    ReplayConfig, ResizeConfig, SeamsConfig, VideoConfig,
+};
+use rmr::debug::{create_debug_image, create_map_image};
+use rmr::energy::EnergyFunction;
+use rmr::seam::{read_seams, write_seams, Seam, SeamFormat};
+use rmr::video::{make_animation_carver, TemporalCarver};
 
 fn main() -> Result<()> {
     pretty_env_logger::init();
@@ -19,12 +32,32 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn run(mut config: Config) -> Result<()> {
-    log::info!("loading: {:?}", &config.input_path);
-    let mut image = image::open(&config.input_path)?;
-    let carver = Rc::new(RefCell::new(Carver::new(&image)));
+fn run(config: Config) -> Result<()> {
+    match config.command {
+        Command::Resize(config) => resize(config),
+        Command::Remove(config) => remove(config),
+        Command::Energy(config) => energy(config),
+        Command::Seams(config) => seams(config),
+        Command::Inspect(config) => inspect(config),
+        Command::Replay(config) => replay(config),
+        Command::Video(config) => video(config),
+    }
+}
 
-    let dimensions = get_target_dimensions(&image, &config)?;
+fn resize(config: ResizeConfig) -> Result<()> {
+    log::info!("loading: {:?}", &config.input_path);
+    let (mut image, input_format) = match read_input(&config.input_path, config.max_memory)? {
+        (Input::Image(image), input_format) => (image, input_format),
+        (Input::Animation(frames), input_format) => {
+            return resize_animation(config, frames, input_format);
+        }
+    };
+    if config.is_trim() {
+        return resize_trim(config, image, input_format);
+    }
+    let dimensions = config
+        .target
+        .get_target_dimensions(get_dimensions(&image))?;
     log::info!(
         "target dimensions [{}]",
         dimensions
@@ -34,12 +67,52 @@ fn run(mut config: Config) -> Result<()> {
             .collect::<Vec<_>>()
             .join(", ")
     );
+    if dimensions.len() > 1 && is_stdio(&config.get_output_path("")) {
+        bail!("can't write more than one image to stdout");
+    }
+    if is_stdio(&config.get_output_path("")) && !config.carve.layer.is_empty() {
+        bail!("can't write layers when writing to stdout");
+    }
+    if dimensions.len() > 1 && config.seams_out.is_some() {
+        bail!("can't write seams for more than one target size");
+    }
+    if let Some(max_memory) = config.max_memory {
+        let size = get_dimensions(&image);
+        let needed = dimensions
+            .iter()
+            .map(|&target| Carver::estimate_memory(size, target))
+            .max()
+            .unwrap_or(0);
+        if needed > max_memory {
+            log::info!("carving needs about {}", format_memory(needed));
+            return resize_compact(config, image, input_format, &dimensions);
+        }
+    }
+    let mut carver = config
+        .carve
+        .build_carver(&image)?
+        .with_strategy(config.strategy);
+    if let Some(hybrid) = config.get_hybrid() {
+        carver = carver.with_hybrid(hybrid);
+    }
+    let carver = Rc::new(RefCell::new(show_progress(carver)));
+
     for (width, height) in dimensions.iter().cloned() {
         log::info!("cloning carver");
         let carver = carver.clone();
         log::info!("resizing to: {:?}", (width, height));
         let scaled_image = carver.borrow_mut().resize(width, height);
+        clear_progress();
         log::info!("finished resizing");
+        if config.hybrid {
+            let report = carver.borrow().get_report();
+            eprintln!(
+                "{:?}: carved {:?} seams, scaled {:?} pixels",
+                (width, height),
+                report.carved,
+                report.scaled
+            );
+        }
 
         let mut suffix = Cow::Borrowed("");
         if dimensions.len() > 1 {
@@ -47,65 +120,439 @@ fn run(mut config: Config) -> Result<()> {
         }
         let path = config.get_output_path(&suffix);
         log::info!("saving output image");
-        save_image_to_path(&scaled_image, path)?;
+        save_image_to_path(&scaled_image, &path, input_format, &config.output)?;
+        save_layers(&carver.borrow(), &path, &config.output)?;
 
         if let Some(debug_path) = &config.debug_path {
             let debug_image = create_debug_image(&mut image, carver.borrow().get_removed_points());
             log::info!("saving debug image");
-            save_image_to_path(&debug_image, debug_path)?;
+            save_image_to_path(&debug_image, debug_path, input_format, &config.output)?;
+        }
+
+        if let Some(seams_path) = &config.seams_out {
+            save_seams_to_path(carver.borrow().get_seams(), seams_path, config.seams_format)?;
         }
     }
 
     Ok(())
 }
 
-fn get_target_dimensions(image: &DynamicImage, config: &Config) -> Result<Vec<(usize, usize)>> {
-    let (width, height) = image.dimensions();
-    let dims @ (width, height) = (width as usize, height as usize);
-
-    if let Some(dimensions) = &config.dimensions {
-        return collect_both_dimensions(&dimensions.0, &dimensions.1, dims);
-    }
-
-    match (&config.width, &config.height) {
-        (None, None) => bail!("no resize dimensions specified"),
-        (None, Some(height_change)) => Ok(height_change
-            .to_absolutes(height)?
-            .into_iter()
-            .map(|h| (width, h))
-            .collect()),
-        (Some(width_change), None) => Ok(width_change
-            .to_absolutes(width)?
-            .into_iter()
-            .map(|w| (w, height))
-            .collect()),
-        (Some(width_change), Some(height_change)) => {
-            collect_both_dimensions(width_change, height_change, dims)
+/// Resizes with the compact carver, which only supports the plain gradient
+/// energy and seam stiffness.
+fn resize_compact(
+    config: ResizeConfig,
+    image: DynamicImage,
+    input_format: Option<ImageFormat>,
+    dimensions: &[(usize, usize)],
+) -> Result<()> {
+    let carve = &config.carve;
+    let unsupported = [
+        ("--strategy", config.strategy != Strategy::Carve),
+        ("--hybrid", config.hybrid),
+        ("--debug-path", config.debug_path.is_some()),
+        ("--seams-out", config.seams_out.is_some()),
+        ("--seams-per-pass", carve.seams_per_pass != 1),
+        ("--search", carve.search != SearchMode::Full),
+        ("--alpha-aware", carve.alpha_aware),
+        ("--color-space", carve.color_space != ColorSpace::default()),
+        ("--energy", carve.energy != EnergyFunction::default()),
+        ("--saliency", carve.saliency > 0.0),
+        ("--protect-skin", carve.protect_skin),
+        ("regions", !carve.get_mask()?.is_empty()),
+        ("--layer", !carve.layer.is_empty()),
+    ];
+    let unsupported: Vec<_> = unsupported
+        .into_iter()
+        .filter_map(|(option, used)| used.then_some(option))
+        .collect();
+    if !unsupported.is_empty() {
+        bail!(
+            "{} can't be used once a resize needs more than --max-memory",
+            unsupported.join(", ")
+        );
+    }
+
+    let size = get_dimensions(&image);
+    let max_memory = config.max_memory.unwrap_or(usize::MAX);
+    for &(width, height) in dimensions {
+        let needed = CompactCarver::estimate_memory(size, (width, height));
+        if needed > max_memory {
+            bail!(
+                "resizing to {width}x{height} needs about {} even in the compact mode",
+                format_memory(needed)
+            );
         }
+        log::info!(
+            "resizing to {:?} in the compact mode, which needs about {}",
+            (width, height),
+            format_memory(needed)
+        );
+        let carver = CompactCarver::new(&image).with_stiffness(carve.get_stiffness());
+        let output = carver.resize(width, height);
+
+        let path = match dimensions.len() {
+            1 => config.get_output_path(""),
+            _ => config.get_output_path(&format!("{:?}", (width, height))),
+        };
+        save_image_to_path(&output, &path, input_format, &config.output)?;
     }
+    Ok(())
 }
 
-fn collect_both_dimensions(
-    width_change: &Change,
-    height_change: &Change,
-    (width, height): (usize, usize),
-) -> Result<Vec<(usize, usize)>> {
-    let mut res = vec![];
-    for w in width_change.to_absolutes(width)? {
-        for h in height_change.to_absolutes(height)? {
-            res.push((w, h));
+/// Trims away seams that cost less than --max-cost, or than the median pixel
+/// with --auto, and reports the size that leaves.
+fn resize_trim(
+    config: ResizeConfig,
+    mut image: DynamicImage,
+    input_format: Option<ImageFormat>,
+) -> Result<()> {
+    if !config.target.is_empty() {
+        bail!("--max-cost and --auto pick the size themselves, so they can't have a target size");
+    }
+    if config.strategy != Strategy::Carve || config.hybrid || config.max_memory.is_some() {
+        bail!("--max-cost and --auto can't be used with --strategy, --hybrid or --max-memory");
+    }
+    let path = config.get_output_path("");
+    if is_stdio(&path) && !config.carve.layer.is_empty() {
+        bail!("can't write layers when writing to stdout");
+    }
+
+    let mut carver = config.carve.build_carver(&image)?;
+    let max_cost = match config.max_cost {
+        Some(max_cost) => max_cost,
+        None => carver.get_median_energy(),
+    };
+    log::info!("trimming seams costing up to {max_cost} per pixel");
+    let output = carver.trim(max_cost);
+    let (width, height) = carver.get_size();
+    eprintln!(
+        "trimmed to {width}x{height}, carving {:?} seams",
+        carver.get_report().carved
+    );
+
+    save_image_to_path(&output, &path, input_format, &config.output)?;
+    save_layers(&carver, &path, &config.output)?;
+    if let Some(debug_path) = &config.debug_path {
+        let debug_image = create_debug_image(&mut image, carver.get_removed_points());
+        save_image_to_path(&debug_image, debug_path, input_format, &config.output)?;
+    }
+    if let Some(seams_path) = &config.seams_out {
+        save_seams_to_path(carver.get_seams(), seams_path, config.seams_format)?;
+    }
+    Ok(())
+}
+
+/// Resizes every frame of an animation with the same seams, so that the
+/// frames stay aligned.
+fn resize_animation(
+    config: ResizeConfig,
+    frames: Vec<Frame>,
+    input_format: Option<ImageFormat>,
+) -> Result<()> {
+    if config.strategy != Strategy::Carve || config.hybrid {
+        bail!("animations can only be resized with --strategy carve and without --hybrid");
+    }
+    if !config.carve.layer.is_empty() {
+        bail!("layers aren't supported for animations");
+    }
+    if config.is_trim() {
+        bail!("--max-cost and --auto aren't supported for animations");
+    }
+    let delays: Vec<_> = frames.iter().map(Frame::delay).collect();
+    let images: Vec<_> = frames
+        .into_iter()
+        .map(|frame| DynamicImage::ImageRgba8(frame.into_buffer()))
+        .collect();
+    let dimensions = config
+        .target
+        .get_target_dimensions(get_dimensions(&images[0]))?;
+    if dimensions.len() > 1 && is_stdio(&config.get_animation_output_path("")) {
+        bail!("can't write more than one image to stdout");
+    }
+    if dimensions.len() > 1 && config.seams_out.is_some() {
+        bail!("can't write seams for more than one target size");
+    }
+    // Only GIF can hold the result, whatever the animation was read as, so
+    // any other format is turned away before carving.
+    let outputs = dimensions
+        .iter()
+        .map(|&(width, height)| {
+            let path = match dimensions.len() {
+                1 => config.get_animation_output_path(""),
+                _ => config.get_animation_output_path(&format!("{:?}", (width, height))),
+            };
+            let format = config
+                .output
+                .get_output_format(&path, Some(ImageFormat::Gif))?;
+            check_animation_format(format)?;
+            Ok((path, format))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    log::info!("finding seams for {} frames", images.len());
+    let carver = make_animation_carver(&images, |image| config.carve.build_carver(image))?;
+    let carver = show_progress(carver);
+    for (&(width, height), (path, format)) in dimensions.iter().zip(outputs) {
+        let mut carver = carver.clone();
+        log::info!("resizing to: {:?}", (width, height));
+        carver.resize(width, height);
+        clear_progress();
+        let frames = images
+            .iter()
+            .zip(&delays)
+            .map(|(image, &delay)| {
+                let image = Carver::replay(carver.get_seams(), image)?;
+                Ok(Frame::from_parts(image.to_rgba8(), 0, 0, delay))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        log::info!("saving {} frames to {:?}", frames.len(), path);
+        write_animation(frames, &path, format)?;
+
+        if let Some(debug_path) = &config.debug_path {
+            let mut first = images[0].clone();
+            let debug_image = create_debug_image(&mut first, carver.get_removed_points());
+            save_image_to_path(&debug_image, debug_path, input_format, &config.output)?;
+        }
+        if let Some(seams_path) = &config.seams_out {
+            save_seams_to_path(carver.get_seams(), seams_path, config.seams_format)?;
         }
     }
-    Ok(res)
+    Ok(())
 }
 
-fn save_image_to_path<P: AsRef<Path>>(image: &DynamicImage, path: P) -> Result<()> {
+fn remove(config: RemoveConfig) -> Result<()> {
+    if !config.carve.get_mask()?.has_removals() {
+        bail!("no regions to remove, use --remove-rect, --remove-poly or --regions");
+    }
+    log::info!("loading: {:?}", &config.input_path);
+    let (image, input_format) = read_image(&config.input_path, config.decode.max_memory)?;
+    let path = config.get_output_path();
+    if is_stdio(&path) && !config.carve.layer.is_empty() {
+        bail!("can't write layers when writing to stdout");
+    }
+    let mut carver = config.carve.build_carver(&image)?;
+    let output = carver.remove_marked(config.restore);
+    save_image_to_path(&output, &path, input_format, &config.output)?;
+    save_layers(&carver, &path, &config.output)
+}
+
+fn energy(config: EnergyConfig) -> Result<()> {
+    log::info!("loading: {:?}", &config.input_path);
+    let (image, input_format) = read_image(&config.input_path, config.decode.max_memory)?;
+    let mut carver = config.carve.build_carver(&image)?;
+
+    let energy_image = create_map_image(&carver.get_energy_map());
+    save_image_to_path(
+        &energy_image,
+        config.get_output_path(),
+        input_format,
+        &config.output,
+    )?;
+
+    if let Some(cost_path) = &config.cost_path {
+        let cost_image = create_map_image(&carver.get_cost_map());
+        save_image_to_path(&cost_image, cost_path, input_format, &config.output)?;
+    }
+    Ok(())
+}
+
+fn seams(config: SeamsConfig) -> Result<()> {
+    log::info!("loading: {:?}", &config.input_path);
+    let (image, _) = read_image(&config.input_path, config.decode.max_memory)?;
+    let carver = config.carve.build_carver(&image)?;
+
+    let seams = carver.find_seams(config.direction, config.count);
+    let path = config.output_path.as_deref().unwrap_or(Path::new(STDIO));
+    save_seams_to_path(&seams, path, config.seams_format)
+}
+
+fn inspect(config: InspectConfig) -> Result<()> {
+    log::info!("loading: {:?}", &config.input_path);
+    let (image, input_format) = read_image(&config.input_path, config.decode.max_memory)?;
+
+    let (width, height) = get_dimensions(&image);
+    println!("dimensions: {width}x{height}");
+    if let Some(format) = input_format {
+        println!("format: {format:?}");
+    }
+    let targets = match config.target.is_empty() {
+        true => vec![(width, height)],
+        false => config.target.get_target_dimensions((width, height))?,
+    };
+    let decoded = image.as_bytes().len();
+    for (w, h) in targets {
+        println!(
+            "memory to {w}x{h}: about {}, or {} with --max-memory",
+            format_memory(decoded + Carver::estimate_memory((width, height), (w, h))),
+            format_memory(decoded + CompactCarver::estimate_memory((width, height), (w, h)))
+        );
+    }
+
+    let carver = config.carve.build_carver(&image)?;
+    println!(
+        "safe shrink: {} columns, {} rows",
+        carver.get_safe_shrink(Direction::Vertical),
+        carver.get_safe_shrink(Direction::Horizontal)
+    );
+    if !config.target.is_empty() {
+        for (w, h) in config.target.get_target_dimensions((width, height))? {
+            println!("cost to {w}x{h}: {}", carver.estimate_cost(w, h));
+        }
+    }
+    Ok(())
+}
+
+fn replay(config: ReplayConfig) -> Result<()> {
+    let format = config
+        .seams_format
+        .unwrap_or_else(|| SeamFormat::from_path(&config.seams));
+    let file = File::open(&config.seams).with_context(|| format!("opening {:?}", config.seams))?;
+    let seams = read_seams(BufReader::new(file), format)?;
+
+    log::info!("loading: {:?}", &config.input_path);
+    let (image, input_format) = read_image(&config.input_path, config.decode.max_memory)?;
+    log::info!("replaying {} seams", seams.len());
+    let output = Carver::replay(&seams, &image)?;
+    save_image_to_path(
+        &output,
+        config.get_output_path(),
+        input_format,
+        &config.output,
+    )
+}
+
+fn video(config: VideoConfig) -> Result<()> {
+    if !config.carve.layer.is_empty() {
+        bail!("layers aren't supported for frame sequences");
+    }
+    let frames = config.input_pattern.find_frames();
+    let Some(&first) = frames.first() else {
+        bail!("no frames found at {:?}", config.input_pattern.path(1));
+    };
+    let (image, _) = read_image(&config.input_pattern.path(first), config.decode.max_memory)?;
+    let size = get_dimensions(&image);
+    let dimensions = config.target.get_target_dimensions(size)?;
+    let [(width, height)] = dimensions[..] else {
+        bail!("can't resize a frame sequence to more than one size");
+    };
+    log::info!("resizing {} frames to: {:?}", frames.len(), (width, height));
+
+    let mut temporal = TemporalCarver::new(config.coherence, config.coherence_radius);
+    for number in frames {
+        let input_path = config.input_pattern.path(number);
+        log::info!("loading: {:?}", &input_path);
+        let (image, input_format) = read_image(&input_path, config.decode.max_memory)?;
+        if get_dimensions(&image) != size {
+            bail!(
+                "{:?} is {}x{}, not {}x{} like the first frame",
+                input_path,
+                image.width(),
+                image.height(),
+                size.0,
+                size.1
+            );
+        }
+        let carver = config.carve.build_carver(&image)?;
+        let output = temporal.carve_frame(carver, width, height);
+        save_image_to_path(
+            &output,
+            config.output_pattern.path(number),
+            input_format,
+            &config.output,
+        )?;
+    }
+    Ok(())
+}
+
+/// How many characters wide the progress bar is.
+const PROGRESS_WIDTH: usize = 30;
+
+/// Draws a progress bar on stderr while the carver resizes, unless stderr
+/// isn't a terminal or log messages would be drawn over it.
+fn show_progress(carver: Carver) -> Carver {
+    if !io::stderr().is_terminal() || log::log_enabled!(log::Level::Info) {
+        return carver;
+    }
+    let drawn = Cell::new(None);
+    carver.with_observer(move |done: usize, total: usize| {
+        let percent = (done * 100 / total.max(1)).min(100);
+        if drawn.replace(Some(percent)) == Some(percent) {
+            return;
+        }
+        let filled = percent * PROGRESS_WIDTH / 100;
+        eprint!(
+            "\r[{}{}] {percent:>3}% of {total} seams",
+            "#".repeat(filled),
+            " ".repeat(PROGRESS_WIDTH - filled)
+        );
+    })
+}
+
+/// Clears the progress bar once a resize is done.
+fn clear_progress() {
+    if io::stderr().is_terminal() && !log::log_enabled!(log::Level::Info) {
+        eprint!("\r\x1b[K");
+    }
+}
+
+/// Formats a number of bytes with a binary suffix, as in `1.5 GiB`.
+fn format_memory(bytes: usize) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{value:.1} {}", units[unit]),
+    }
+}
+
+fn get_dimensions(image: &DynamicImage) -> (usize, usize) {
+    let (width, height) = image.dimensions();
+    (width as usize, height as usize)
+}
+
+fn save_image_to_path<P: AsRef<Path>>(
+    image: &DynamicImage,
+    path: P,
+    input_format: Option<ImageFormat>,
+    options: &OutputOptions,
+) -> Result<()> {
     let path = path.as_ref();
+    let format = options.get_output_format(path, input_format)?;
     log::info!(
-        "saving image of size {:?} to {:?}",
+        "saving image of size {:?} to {:?} as {:?}",
         image.dimensions(),
-        path
+        path,
+        format
     );
-    image.save(path)?;
+    write_image(image, path, format, &options.get_encode_options())
+}
+
+fn save_layers(carver: &Carver, output_path: &Path, options: &OutputOptions) -> Result<()> {
+    // Layers hold data rather than pictures, so only lossless settings apply.
+    let encode_options = EncodeOptions {
+        png_compression: options.png_compression,
+        ..EncodeOptions::default()
+    };
+    for layer in carver.get_layers() {
+        let path = get_layer_path(output_path, &layer.name);
+        log::info!("saving layer {} to {:?}", layer.name, path);
+        write_image(&layer.to_image(), &path, ImageFormat::Png, &encode_options)?;
+    }
     Ok(())
 }
+
+fn save_seams_to_path(seams: &[Seam], path: &Path, format: Option<SeamFormat>) -> Result<()> {
+    let format = format.unwrap_or_else(|| SeamFormat::from_path(path));
+    log::info!("saving {} seams to {:?} as {:?}", seams.len(), path, format);
+    if is_stdio(path) {
+        return write_seams(seams, io::stdout().lock(), format);
+    }
+    let file = File::create(path).with_context(|| format!("creating {path:?}"))?;
+    write_seams(seams, BufWriter::new(file), format)
+}
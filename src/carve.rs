@@ -1,9 +1,226 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
+use std::fmt;
 use std::mem;
+use std::ops::Range;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
 
+use anyhow::{bail, Context, Error, Result};
+
+use image::imageops::FilterType;
 use image::{DynamicImage, GenericImage};
 
-use crate::energy::PixelEnergyPoint;
+use crate::energy::{EnergyModifier, EnergyOptions, PixelEnergyPoint, MAX_GRADIENT_ENERGY};
 use crate::grid::{Grid, Token};
+use crate::layer::{
+    collect_layer, interpolate_layers, layer_energy, Layer, LayerSamples, LayerSlot,
+};
+use crate::remap::CarveMap;
+use crate::saliency::spectral_residual;
+use crate::seam::{Seam, SeamKind};
+
+/// The bytes a position shared between a point and its tokens takes up on
+/// the heap, counting both reference counts and allocator overhead.
+const SHARED_POSITION_SIZE: usize = 48;
+
+/// The columns a seam through `x` may come from in the row above.
+fn parents_within(x: usize, width: usize, max_drift: usize) -> Range<usize> {
+    x.saturating_sub(max_drift)..(x + max_drift + 1).min(width)
+}
+
+/// Limits how far a seam may wander sideways between neighbouring rows.
+///
+/// Stiffer seams follow the content less closely, but straight structures
+/// such as buildings and lines of text warp far less.
+#[derive(Clone, Copy, Debug)]
+pub struct Stiffness {
+    /// The number of columns a seam may move per row. Zero only allows
+    /// straight seams, which degenerates into removing whole columns.
+    pub max_drift: usize,
+    /// Added to a seam's path cost for every column it moves sideways.
+    pub diagonal_penalty: u32,
+}
+
+impl Default for Stiffness {
+    fn default() -> Self {
+        Self {
+            max_drift: 1,
+            diagonal_penalty: 0,
+        }
+    }
+}
+
+/// Settings for mixing seam carving with uniform scaling.
+///
+/// Pure seam carving breaks down for large size changes, so carving stops
+/// once either limit is reached and the rest of the change is scaled.
+#[derive(Clone, Copy, Debug)]
+pub struct Hybrid {
+    /// The average energy per pixel a seam may have before carving stops.
+    pub max_seam_cost: Option<u32>,
+    /// The share of the change, from 0 to 1, that may be carved.
+    pub max_carve_fraction: f32,
+    /// The filter used to scale the rest of the way.
+    pub filter: FilterType,
+}
+
+impl Hybrid {
+    fn carve_target(&self, initial: usize, target: usize) -> usize {
+        let fraction = self.max_carve_fraction.clamp(0.0, 1.0);
+        let distance = (initial.abs_diff(target) as f32 * fraction) as usize;
+        if target > initial {
+            initial + distance
+        } else {
+            initial - distance
+        }
+    }
+}
+
+impl Default for Hybrid {
+    fn default() -> Self {
+        Self {
+            max_seam_cost: None,
+            max_carve_fraction: 0.5,
+            filter: FilterType::Lanczos3,
+        }
+    }
+}
+
+/// How a carver reduces an image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Removes the lowest energy seams.
+    #[default]
+    Carve,
+    /// Keeps the window of the target size that holds the most energy.
+    Crop,
+    /// Carves or crops, whichever loses less energy. Both are measured on
+    /// the energy from before the resize, so carving isn't let off for
+    /// pixels that only became cheap once the seams next to them were gone.
+    Auto,
+}
+
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "carve" => Ok(Strategy::Carve),
+            "crop" => Ok(Strategy::Crop),
+            "auto" => Ok(Strategy::Auto),
+            _ => bail!("unknown strategy: {s}"),
+        }
+    }
+}
+
+/// How a carver searches for each seam.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Works out the path cost of every pixel for every seam.
+    #[default]
+    Full,
+    /// Finds seams on a copy of the energy halved in size `levels` times,
+    /// then refines each at full size within `band` columns of where the
+    /// small seam ran. Much faster for large reductions, but seams can't
+    /// take detours the small copy doesn't show.
+    Pyramid { levels: usize, band: usize },
+}
+
+impl FromStr for SearchMode {
+    type Err = Error;
+
+    /// Parses `full`, or `pyramid` with two levels and a band of eight.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "full" => Ok(SearchMode::Full),
+            "pyramid" => Ok(SearchMode::Pyramid { levels: 2, band: 8 }),
+            _ => bail!("unknown search mode: {s}"),
+        }
+    }
+}
+
+/// The way a seam runs through an image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Direction {
+    /// Runs from top to bottom and narrows the image.
+    #[default]
+    Vertical,
+    /// Runs from left to right and shortens the image.
+    Horizontal,
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "vertical" => Ok(Direction::Vertical),
+            "horizontal" => Ok(Direction::Horizontal),
+            _ => bail!("unknown direction: {s}"),
+        }
+    }
+}
+
+/// How much of a resize was done by each method, per dimension.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResizeReport {
+    /// The number of pixels cropped away as `(width, height)`.
+    pub cropped: (usize, usize),
+    /// The number of seams carved as `(width, height)`.
+    pub carved: (usize, usize),
+    /// The number of pixels scaled as `(width, height)`.
+    pub scaled: (usize, usize),
+}
+
+/// Watches the progress of a resize.
+pub trait ResizeObserver {
+    /// Called after each seam with the number of seams carved so far and
+    /// the number the resize carves in all. Growing and shrinking can stop
+    /// short of the total, so it may never be reached.
+    fn on_seam(&self, done: usize, total: usize);
+}
+
+impl<F: Fn(usize, usize)> ResizeObserver for F {
+    fn on_seam(&self, done: usize, total: usize) {
+        self(done, total)
+    }
+}
+
+/// The error [`Carver::try_resize`] returns when a resize is cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the resize was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// A removed seam along with the points it took out, in the order of the
+/// seam's points.
+#[derive(Clone)]
+struct Removal {
+    seam: Seam,
+    points: Vec<PixelEnergyPoint>,
+}
+
+/// The seams removed since the carver last grew or cropped, and those undone
+/// since, so that they can be undone and redone.
+#[derive(Clone, Default)]
+struct History {
+    done: Vec<Removal>,
+    undone: Vec<Removal>,
+}
 
 #[derive(Clone)]
 pub struct Carver {
@@ -11,96 +228,1042 @@ pub struct Carver {
     removed_points: Vec<(usize, usize)>,
     dirty_points: Vec<Token>,
     path: Vec<(usize, usize)>,
+    stiffness: Stiffness,
+    seams_per_pass: usize,
+    hybrid: Option<Hybrid>,
+    strategy: Strategy,
+    search_mode: SearchMode,
+    energy_options: EnergyOptions,
+    removed_energy: u64,
+    report: ResizeReport,
+    seams: Vec<Seam>,
+    source_size: (usize, usize),
+    output_size: (usize, usize),
+    layers: Vec<LayerSlot>,
+    layer_samples: LayerSamples,
+    observer: Option<Rc<dyn ResizeObserver>>,
+    /// The seams carved so far and in all by the current resize.
+    progress: (usize, usize),
+    cancellation: Option<Arc<AtomicBool>>,
+    history: Option<History>,
 }
 
 impl Carver {
     pub fn new(image: &DynamicImage) -> Self {
         let grid = image.into();
+        let size = (image.width() as usize, image.height() as usize);
         Self {
             grid,
             removed_points: vec![],
             dirty_points: vec![],
             path: vec![],
+            stiffness: Stiffness::default(),
+            seams_per_pass: 1,
+            hybrid: None,
+            strategy: Strategy::default(),
+            search_mode: SearchMode::default(),
+            energy_options: EnergyOptions::default(),
+            removed_energy: 0,
+            report: ResizeReport::default(),
+            seams: vec![],
+            source_size: size,
+            output_size: size,
+            layers: vec![],
+            layer_samples: LayerSamples::default(),
+            observer: None,
+            progress: (0, 0),
+            cancellation: None,
+            history: None,
         }
     }
 
+    pub fn with_stiffness(mut self, stiffness: Stiffness) -> Self {
+        self.stiffness = stiffness;
+        self
+    }
+
+    /// Removes up to `seams_per_pass` non-overlapping seams each time the
+    /// energy is recomputed. This trades quality for speed.
+    pub fn with_seams_per_pass(mut self, seams_per_pass: usize) -> Self {
+        self.seams_per_pass = seams_per_pass.max(1);
+        self
+    }
+
+    /// Finishes a resize with uniform scaling once carving stops paying off.
+    pub fn with_hybrid(mut self, hybrid: Hybrid) -> Self {
+        self.hybrid = Some(hybrid);
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets how seams are searched for. Only single seam passes use the
+    /// pyramid search.
+    pub fn with_search_mode(mut self, search_mode: SearchMode) -> Self {
+        self.search_mode = search_mode;
+        self
+    }
+
+    /// Reports each seam of a resize to the observer.
+    pub fn with_observer(mut self, observer: impl ResizeObserver + 'static) -> Self {
+        self.observer = Some(Rc::new(observer));
+        self
+    }
+
+    /// Stops carving before the next seam once the token is set, which may
+    /// happen from another thread. The image is left partly carved.
+    pub fn with_cancellation(mut self, token: Arc<AtomicBool>) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Keeps the points of every removed seam so that seams can be undone
+    /// and redone, which takes about as much memory again as the points.
+    pub fn with_history(mut self) -> Self {
+        self.history = Some(History::default());
+        self
+    }
+
+    pub fn with_energy_options(mut self, energy_options: EnergyOptions) -> Self {
+        self.energy_options = energy_options;
+        if energy_options.uses_lab() {
+            for pep in self.grid.iter_mut() {
+                pep.calculate_lab();
+            }
+        }
+        if energy_options.uses_saliency() {
+            self.calculate_saliency();
+        }
+        self
+    }
+
+    /// Adds the biases from the given modifier to the grid. They move along
+    /// with their pixels as the image is carved.
+    pub fn with_energy_modifier(mut self, modifier: &dyn EnergyModifier) -> Self {
+        let bias = modifier.bias(&self.rebuild_image());
+        for (x, y, pep) in self.grid.coord_iter_mut() {
+            pep.bias = pep.bias.saturating_add(bias[y][x]);
+        }
+        self
+    }
+
+    /// Attaches a layer of extra values to every pixel, which is carved along
+    /// with the image. The layer has to be the same size as the image.
+    pub fn with_layer(mut self, layer: Layer) -> Result<Self> {
+        let size = (self.grid.width(), self.grid.height());
+        if (layer.width, layer.height) != size {
+            bail!(
+                "layer {} is {}x{}, not {}x{}",
+                layer.name,
+                layer.width,
+                layer.height,
+                size.0,
+                size.1
+            );
+        }
+        if self.layers.is_empty() {
+            // Points share a sample until there are layer values to keep.
+            for (sample, pep) in self.grid.iter_mut().enumerate() {
+                pep.layer_sample = sample;
+            }
+            self.layer_samples = LayerSamples::new(size.0 * size.1);
+        }
+        let offset = self.layer_samples.stride();
+        self.layer_samples.widen(layer.channels);
+        for (x, y, pep) in self.grid.coord_iter() {
+            let values = &mut self.layer_samples.get_mut(pep.layer_sample)[offset..];
+            values.copy_from_slice(layer.get(x, y));
+        }
+        self.layers.push(LayerSlot {
+            name: layer.name,
+            offset,
+            channels: layer.channels,
+            interpolation: layer.interpolation,
+            energy_weight: layer.energy_weight,
+        });
+        Ok(self)
+    }
+
     pub fn resize(&mut self, width: usize, height: usize) -> DynamicImage {
         let initial_width = self.grid.width();
         let initial_height = self.grid.height();
 
+        match self.strategy {
+            Strategy::Carve => {}
+            Strategy::Crop => self.crop_to(width, height),
+            Strategy::Auto => {
+                let energy = self.get_energy_by_original_position();
+                let mut carver = self.clone().with_strategy(Strategy::Carve);
+                let removed = carver.removed_points.len();
+                let image = carver.resize(width, height);
+                let carved_energy: u64 = carver.removed_points[removed..]
+                    .iter()
+                    .filter_map(|position| energy.get(position))
+                    .map(|&energy| energy as u64)
+                    .sum();
+                let cropped_energy = self.get_cropped_energy(width, height);
+                log::info!("carving loses {carved_energy}, cropping loses {cropped_energy}");
+                if carved_energy <= cropped_energy {
+                    *self = carver.with_strategy(Strategy::Auto);
+                    return image;
+                }
+                self.crop_to(width, height);
+            }
+        }
+
+        let cropped = (
+            initial_width - self.grid.width(),
+            initial_height - self.grid.height(),
+        );
+        let image = self.carve_to(width, height);
+        self.report.cropped = cropped;
+        image
+    }
+
+    /// Resizes like [`resize`](Carver::resize), but fails with [`Cancelled`]
+    /// if the cancellation token was set before the resize finished.
+    pub fn try_resize(&mut self, width: usize, height: usize) -> Result<DynamicImage> {
+        let image = self.resize(width, height);
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(image)
+    }
+
+    /// Whether the cancellation token has been set.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(|token| token.load(AtomicOrdering::Relaxed))
+    }
+
+    fn carve_to(&mut self, width: usize, height: usize) -> DynamicImage {
+        let initial_width = self.grid.width();
+        let initial_height = self.grid.height();
+
+        let (carve_width, carve_height) = match &self.hybrid {
+            None => (width, height),
+            Some(hybrid) => (
+                hybrid.carve_target(initial_width, width),
+                hybrid.carve_target(initial_height, height),
+            ),
+        };
+
+        self.progress = (
+            0,
+            carve_width.abs_diff(initial_width) + carve_height.abs_diff(initial_height),
+        );
         self.calculate_all_pixel_energy();
-        let mut width_changed = false;
+        let carved_width = self.carve_width_to(carve_width);
+
+        if carved_width > 0 && carve_height != initial_height {
+            self.calculate_all_pixel_energy();
+        }
 
-        match width.cmp(&initial_height) {
-            std::cmp::Ordering::Equal => {}
-            std::cmp::Ordering::Greater => {
-                self.grow_distance(width - initial_width);
-                width_changed = true;
+        let mut carved_height = 0;
+        if carve_height != initial_height && !self.is_cancelled() {
+            self.grid.rotate();
+            carved_height = self.carve_width_to(carve_height);
+            self.grid.rotate();
+        }
+
+        let image = self.rebuild_image();
+        let (image_width, image_height) = (image.width() as usize, image.height() as usize);
+        self.output_size = (width, height);
+        self.report = ResizeReport {
+            cropped: (0, 0),
+            carved: (carved_width, carved_height),
+            scaled: (width.abs_diff(image_width), height.abs_diff(image_height)),
+        };
+
+        match &self.hybrid {
+            Some(hybrid) if (image_width, image_height) != (width, height) => {
+                log::info!("scaling the remaining {:?}", self.report.scaled);
+                image.resize_exact(width as u32, height as u32, hybrid.filter)
             }
-            std::cmp::Ordering::Less => {
-                self.shrink_distance(initial_width - width);
-                width_changed = true;
+            _ => image,
+        }
+    }
+
+    /// Carves away every pixel marked for removal with a negative bias, such
+    /// as the remove regions of a [`Mask`](crate::mask::Mask). Seams run in
+    /// whichever direction needs fewer of them. With `restore` set the image
+    /// is then grown back to its original size.
+    pub fn remove_marked(&mut self, restore: bool) -> DynamicImage {
+        let initial_width = self.grid.width();
+        let initial_height = self.grid.height();
+
+        // Energy is clamped at zero, so marked pixels can only be made free
+        // and seams would still go around them when the rest of their path
+        // is expensive. Raising every other pixel makes crossing a marked
+        // pixel worth more than anything else.
+        let offset = self.get_unmarked_offset();
+        self.offset_unmarked_bias(offset);
+        let direction = self.get_removal_direction();
+        if direction == Direction::Horizontal {
+            self.grid.rotate();
+        }
+        self.calculate_all_pixel_energy();
+        let mut carved = 0;
+        let mut marked = self.count_marked_points();
+        while marked > 0 && self.grid.width() > 1 && !self.is_cancelled() {
+            if self.shrink_distance(1) == 0 {
+                break;
             }
+            carved += 1;
+            let remaining = self.count_marked_points();
+            if remaining == marked {
+                log::warn!("stopped with {remaining} marked pixels no seam would take");
+                break;
+            }
+            marked = remaining;
+        }
+        if direction == Direction::Horizontal {
+            self.grid.rotate();
         }
+        self.offset_unmarked_bias(-offset);
+        log::info!("removed {carved} {direction:?} seams");
 
-        if width_changed && height != initial_height {
-            self.calculate_all_pixel_energy();
+        if restore {
+            self.carve_to(initial_width, initial_height)
+        } else {
+            self.output_size = (self.grid.width(), self.grid.height());
+            self.rebuild_image()
         }
+    }
 
-        match height.cmp(&initial_height) {
-            std::cmp::Ordering::Equal => {}
-            std::cmp::Ordering::Greater => {
-                self.grid.rotate();
-                self.grow_distance(height - initial_height);
-                self.grid.rotate();
-            }
-            std::cmp::Ordering::Less => {
-                self.grid.rotate();
-                self.shrink_distance(initial_height - height);
-                self.grid.rotate();
+    /// Calculates the energy of every pixel.
+    pub fn get_energy_map(&mut self) -> Vec<Vec<u32>> {
+        self.calculate_all_pixel_energy();
+        self.get_pixel_energy()
+    }
+
+    /// Calculates the cost of the cheapest vertical seam ending at each pixel.
+    pub fn get_cost_map(&mut self) -> Vec<Vec<u32>> {
+        self.calculate_all_pixel_energy();
+        self.calculate_energy();
+        self.get_path_energy()
+    }
+
+    /// Finds the `count` seams that shrinking the image would remove, in the
+    /// order they would be removed, without modifying the image.
+    pub fn find_seams(&self, direction: Direction, count: usize) -> Vec<Seam> {
+        let mut carver = self.clone();
+        carver.observer = None;
+        carver.cancellation = None;
+        carver.history = None;
+        carver.seams.clear();
+        if direction == Direction::Horizontal {
+            carver.grid.rotate();
+        }
+        let count = count.min(carver.grid.width() - 1);
+        carver.calculate_all_pixel_energy();
+        carver.shrink_distance(count);
+        carver.seams
+    }
+
+    /// Removes seams in the given direction one at a time as the returned
+    /// iterator is advanced, yielding each after it is removed. Stop
+    /// whenever you like; the carver keeps every seam removed so far.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate image;
+    /// # extern crate rmr;
+    /// # use image::DynamicImage;
+    /// # use rmr::carve::{Carver, Direction};
+    /// # fn main() {
+    /// let image = DynamicImage::new_rgb8(20, 10);
+    /// let mut carver = Carver::new(&image);
+    /// let cheap = carver
+    ///     .seams(Direction::Vertical)
+    ///     .take_while(|seam| seam.cost < 1_000)
+    ///     .take(5)
+    ///     .count();
+    /// assert_eq!(5, cheap);
+    /// assert_eq!((15, 10), carver.get_size());
+    /// # }
+    /// ```
+    pub fn seams(&mut self, direction: Direction) -> Seams<'_> {
+        if direction == Direction::Horizontal {
+            self.grid.rotate();
+        }
+        self.progress = (0, self.grid.width() - 1);
+        self.calculate_all_pixel_energy();
+        Seams {
+            carver: self,
+            direction,
+        }
+    }
+
+    /// Applies recorded seams to another image of the same size, reproducing
+    /// exactly the removals and insertions of the carve they came from. This
+    /// is for carving depth maps, masks and the like to match a photo.
+    /// Cropping and scaling aren't seams, so resizes that used them can't be
+    /// replayed in full.
+    pub fn replay(seams: &[Seam], image: &DynamicImage) -> Result<DynamicImage> {
+        let mut carver = Carver::new(image);
+        for seam in seams {
+            carver.apply_seam(seam)?;
+        }
+        Ok(carver.rebuild_image())
+    }
+
+    /// The total energy of the pixels a resize to the given size would carve
+    /// or crop away. Growing removes nothing, so only shrinking costs.
+    pub fn estimate_cost(&self, width: usize, height: usize) -> u64 {
+        let mut carver = self.clone();
+        carver.observer = None;
+        carver.cancellation = None;
+        carver.history = None;
+        carver.resize(width, height);
+        carver.removed_energy - self.removed_energy
+    }
+
+    /// Estimates the most memory in bytes a carver needs to resize an image
+    /// between the given sizes, not counting the decoded input image. Each
+    /// pixel is a whole point in the grid, and growing finds its seams on a
+    /// copy of the grid.
+    pub fn estimate_memory(
+        (width, height): (usize, usize),
+        (target_width, target_height): (usize, usize),
+    ) -> usize {
+        let largest = width.max(target_width) * height.max(target_height);
+        let mut bytes = largest * Grid::<PixelEnergyPoint>::point_size();
+        if target_width > width || target_height > height {
+            bytes += width * height * Grid::<PixelEnergyPoint>::point_size();
+        }
+        // Every pixel next to a seam is given a position shared with its
+        // tokens, which it keeps for the rest of the carve.
+        let seams = width.abs_diff(target_width) + height.abs_diff(target_height);
+        let touched = (4 * seams * width.max(height)).min(largest);
+        bytes + touched * SHARED_POSITION_SIZE + target_width * target_height * 4
+    }
+
+    /// The energy of the median pixel, a threshold for seam costs that
+    /// carving rarely crosses before it cuts into content.
+    pub fn get_median_energy(&mut self) -> u32 {
+        self.calculate_all_pixel_energy();
+        let mut energies: Vec<u32> = self.grid.iter().map(|pep| pep.energy).collect();
+        let middle = energies.len() / 2;
+        let (_, &mut median, _) = energies.select_nth_unstable(middle);
+        median
+    }
+
+    /// Puts back up to `count` of the most recently removed seams and returns
+    /// how many were put back. Only carvers made
+    /// [`with_history`](Carver::with_history) remember their seams, and
+    /// growing or cropping forgets every seam removed before it.
+    pub fn undo(&mut self, count: usize) -> usize {
+        let mut undone = 0;
+        while undone < count {
+            let Some(removal) = self.history.as_mut().and_then(|history| history.done.pop()) else {
+                break;
+            };
+            self.restore_removal(&removal);
+            if let Some(history) = &mut self.history {
+                history.undone.push(removal);
             }
+            undone += 1;
         }
+        self.output_size = self.get_size();
+        undone
+    }
+
+    /// Removes up to `count` of the seams last undone again and returns how
+    /// many were removed. Removing any other seam forgets those undone.
+    pub fn redo(&mut self, count: usize) -> usize {
+        let mut redone = 0;
+        while redone < count {
+            let Some(removal) = self
+                .history
+                .as_mut()
+                .and_then(|history| history.undone.pop())
+            else {
+                break;
+            };
+            self.repeat_removal(removal.seam);
+            redone += 1;
+        }
+        self.output_size = self.get_size();
+        redone
+    }
 
+    /// Removes vertical seams and then horizontal ones for as long as the
+    /// cheapest costs at most `max_seam_cost` per pixel, trimming away the
+    /// parts of the image with little in them. The report gives the number
+    /// of seams removed, and [`get_size`](Carver::get_size) the size reached.
+    pub fn trim(&mut self, max_seam_cost: u32) -> DynamicImage {
+        let (initial_width, initial_height) = self.get_size();
+        let hybrid = self.hybrid;
+        self.hybrid = Some(Hybrid {
+            max_seam_cost: Some(max_seam_cost),
+            ..hybrid.unwrap_or_default()
+        });
+        for direction in [Direction::Vertical, Direction::Horizontal] {
+            self.seams(direction).count();
+        }
+        self.hybrid = hybrid;
+
+        let (width, height) = self.get_size();
+        self.report = ResizeReport {
+            carved: (initial_width - width, initial_height - height),
+            ..ResizeReport::default()
+        };
         self.rebuild_image()
     }
 
+    /// Suggests how many seams can be removed before the cheapest seam costs
+    /// more per pixel than the median pixel, which is roughly where carving
+    /// starts to cut into content. Never more than half the image.
+    pub fn get_safe_shrink(&self, direction: Direction) -> usize {
+        let mut carver = self.clone();
+        carver.observer = None;
+        carver.cancellation = None;
+        carver.history = None;
+        if direction == Direction::Horizontal {
+            carver.grid.rotate();
+        }
+        let median = carver.get_median_energy();
+        carver.hybrid = Some(Hybrid {
+            max_seam_cost: Some(median),
+            ..carver.hybrid.unwrap_or_default()
+        });
+        let width = carver.grid.width();
+        carver.shrink_distance(width / 2)
+    }
+
+    /// The seams carved so far, in the order they were carved. Cropping and
+    /// scaling don't carve seams, so they aren't included.
+    pub fn get_seams(&self) -> &[Seam] {
+        &self.seams
+    }
+
+    /// Maps coordinates between the original image and the output of the
+    /// carves done so far. Call it after [`resize`](Carver::resize) rather
+    /// than getting it back from the resize, which keeps returning just the
+    /// image for its many callers and lets the map follow any later carves.
+    pub fn get_carve_map(&self) -> CarveMap {
+        let mut sources = vec![vec![]; self.grid.height()];
+        for (_, y, pep) in self.grid.coord_iter() {
+            sources[y].push(pep.original_position);
+        }
+        CarveMap::new(sources, self.source_size, self.output_size)
+    }
+
+    /// Rebuilds the attached layers as they are after the carves done so far,
+    /// scaled to the output size if the resize finished by scaling.
+    pub fn get_layers(&self) -> Vec<Layer> {
+        let rows: Vec<_> = (0..self.grid.height())
+            .map(|y| self.grid.get_row(y))
+            .collect();
+        let (width, height) = self.output_size;
+        self.layers
+            .iter()
+            .map(|slot| {
+                let layer = collect_layer(slot, &self.layer_samples, &rows);
+                if (layer.width, layer.height) == (width, height) {
+                    layer
+                } else {
+                    layer.resize(width, height)
+                }
+            })
+            .collect()
+    }
+
+    /// The size of the image as carved so far.
+    pub fn get_size(&self) -> (usize, usize) {
+        (self.grid.width(), self.grid.height())
+    }
+
+    /// The image as carved so far.
+    pub fn get_image(&self) -> DynamicImage {
+        self.rebuild_image()
+    }
+
+    /// The size of the image the carver was created from.
+    pub fn get_source_size(&self) -> (usize, usize) {
+        self.source_size
+    }
+
     pub fn get_removed_points(&self) -> &[(usize, usize)] {
         &self.removed_points
     }
 
-    fn shrink_distance(&mut self, distance: usize) {
-        for _ in 0..distance {
+    /// Describes how much of the last resize was carved and how much was
+    /// left to uniform scaling.
+    pub fn get_report(&self) -> ResizeReport {
+        self.report
+    }
+
+    /// Carves the grid's width towards `target` and returns the number of
+    /// seams that were actually carved.
+    fn carve_width_to(&mut self, target: usize) -> usize {
+        let width = self.grid.width();
+        match target.cmp(&width) {
+            Ordering::Equal => 0,
+            Ordering::Greater => self.grow_distance(target - width),
+            Ordering::Less => self.shrink_distance(width - target),
+        }
+    }
+
+    fn shrink_distance(&mut self, distance: usize) -> usize {
+        let mut removed = 0;
+        if let SearchMode::Pyramid { levels, band } = self.search_mode {
+            // A scale past the grid width would leave nothing to search.
+            let levels = levels.min(self.grid.width().max(1).ilog2() as usize);
+            if levels > 0 && self.seams_per_pass == 1 {
+                removed = self.shrink_distance_by_pyramid(distance, 1 << levels, band);
+            }
+        }
+        // The full search finishes whatever the pyramid could not guide.
+        while removed < distance && !self.is_cancelled() {
             self.calculate_energy();
-            let (start_x, start_y) = self.get_path_start();
-            self.find_path(start_x, start_y);
-            self.remove_path();
+            let count = self.seams_per_pass.min(distance - removed);
+            if count == 1 {
+                let (start_x, start_y) = self.get_path_start();
+                if self.is_too_costly(start_x, start_y) {
+                    break;
+                }
+                self.find_path(start_x, start_y);
+                self.remove_path();
+                self.advance(1);
+                removed += 1;
+            } else {
+                let mut paths = self.find_disjoint_paths(count);
+                paths.retain(|path| !self.is_too_costly(path[0].0, path[0].1));
+                if paths.is_empty() {
+                    break;
+                }
+                removed += paths.len();
+                self.advance(paths.len());
+                self.remove_paths(paths);
+            }
         }
+        removed
     }
 
-    fn grow_distance(&mut self, distance: usize) {
-        let points = self.get_points_removed_by_shrink(distance);
+    /// Shrinks like `shrink_distance`, but guides each seam by one found on
+    /// energy scaled down by `scale`. Every small seam stands for `scale`
+    /// seams at full size. Stops early once the small energy runs out of
+    /// seams, leaving the rest to the full search.
+    fn shrink_distance_by_pyramid(&mut self, distance: usize, scale: usize, band: usize) -> usize {
+        let mut removed = 0;
+        while removed < distance && !self.is_cancelled() {
+            self.calculate_dirty_pixel_energy();
+            let count = (distance - removed).div_ceil(scale);
+            let guides = self.find_coarse_seams(scale, count);
+            let exhausted = guides.len() < count;
+            for mut guide in guides {
+                for _ in 0..scale.min(distance - removed) {
+                    if self.is_cancelled() {
+                        return removed;
+                    }
+                    self.calculate_dirty_pixel_energy();
+                    let (start_x, start_y) = match self.find_banded_path(&guide, band) {
+                        Some(start) => start,
+                        None => {
+                            // The band was too narrow to hold a seam.
+                            self.calculate_energy();
+                            let start = self.get_path_start();
+                            self.find_path(start.0, start.1);
+                            start
+                        }
+                    };
+                    if self.is_too_costly(start_x, start_y) {
+                        return removed;
+                    }
+                    for &(x, y) in &self.path {
+                        if x < guide[y] {
+                            guide[y] -= 1;
+                        }
+                    }
+                    self.remove_path();
+                    self.advance(1);
+                    removed += 1;
+                }
+            }
+            if exhausted {
+                break;
+            }
+        }
+        removed
+    }
+
+    /// Finds up to `count` seams on the energy averaged over blocks of
+    /// `scale` by `scale` pixels, one after another. Each is returned as the
+    /// column it runs through in every row at full size.
+    fn find_coarse_seams(&self, scale: usize, count: usize) -> Vec<Vec<usize>> {
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let coarse_height = height.div_ceil(scale);
+        let mut energy = vec![vec![0u64; width.div_ceil(scale)]; coarse_height];
+        for y in 0..height {
+            for x in 0..width {
+                energy[y / scale][x / scale] += self.grid.get(x, y).energy as u64;
+            }
+        }
+        // Blocks along the right and bottom edges may be partly outside the
+        // image, so every block is averaged rather than summed.
+        let block_size = |start: usize, len: usize| (len - start * scale).min(scale) as u64;
+        for (by, row) in energy.iter_mut().enumerate() {
+            for (bx, block) in row.iter_mut().enumerate() {
+                *block /= block_size(by, height) * block_size(bx, width);
+            }
+        }
+
+        let Stiffness {
+            max_drift,
+            diagonal_penalty,
+        } = self.stiffness;
+        // A block stands for as many rows as columns, so averaging keeps the
+        // penalty in proportion to the energy.
+        let penalty = diagonal_penalty as u64;
+        let mut guides = vec![];
+        while guides.len() < count && energy[0].len() > 1 {
+            let coarse_width = energy[0].len();
+            let mut costs = vec![vec![0u64; coarse_width]; coarse_height];
+            for y in 0..coarse_height {
+                for x in 0..coarse_width {
+                    let parent = match y {
+                        0 => 0,
+                        _ => parents_within(x, coarse_width, max_drift)
+                            .map(|px| costs[y - 1][px] + px.abs_diff(x) as u64 * penalty)
+                            .min()
+                            .unwrap_or(0),
+                    };
+                    costs[y][x] = parent + energy[y][x];
+                }
+            }
+
+            let last = &costs[coarse_height - 1];
+            let mut x = (0..coarse_width).min_by_key(|&x| last[x]).unwrap_or(0);
+            let mut seam = vec![0; coarse_height];
+            for y in (0..coarse_height).rev() {
+                seam[y] = x;
+                energy[y].remove(x);
+                if y > 0 {
+                    x = parents_within(x, coarse_width, max_drift)
+                        .min_by_key(|&px| costs[y - 1][px] + px.abs_diff(x) as u64 * penalty)
+                        .unwrap_or(x);
+                }
+            }
+
+            // Run through the middle of each block, blending between rows so
+            // the full size seam drifts smoothly.
+            let guide = (0..height)
+                .map(|y| {
+                    let row = ((y as f32 + 0.5) / scale as f32 - 0.5)
+                        .clamp(0.0, (coarse_height - 1) as f32);
+                    let (above, below) = (row.floor() as usize, row.ceil() as usize);
+                    let t = row - above as f32;
+                    let column = seam[above] as f32 * (1.0 - t) + seam[below] as f32 * t;
+                    (((column + 0.5) * scale as f32) as usize).min(width - 1)
+                })
+                .collect();
+            guides.push(guide);
+        }
+        guides
+    }
+
+    /// Finds the cheapest seam that stays within `band` columns of the
+    /// guide, working out path costs only inside the band. Returns where the
+    /// seam starts, or `None` if no seam fits.
+    fn find_banded_path(&mut self, guide: &[usize], band: usize) -> Option<(usize, usize)> {
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let ranges: Vec<_> = guide
+            .iter()
+            .map(|&x| {
+                let x = x.min(width - 1);
+                x.saturating_sub(band)..(x + band + 1).min(width)
+            })
+            .collect();
+
+        for y in 0..height {
+            for x in ranges[y].clone() {
+                let energy = self.grid.get(x, y).energy;
+                let path_cost = match y {
+                    0 => energy,
+                    _ => self
+                        .iter_banded_parent_path_costs(&ranges[y - 1], x, y)
+                        .map(|(_, path_cost)| path_cost)
+                        .min()
+                        .unwrap_or(u32::MAX)
+                        .saturating_add(energy),
+                };
+                self.grid.get_mut(x, y).path_cost = path_cost;
+            }
+        }
+
+        let y = height - 1;
+        let start_x = ranges[y]
+            .clone()
+            .min_by_key(|&x| self.grid.get(x, y).path_cost)?;
+        if self.grid.get(start_x, y).path_cost == u32::MAX {
+            return None;
+        }
+        self.path.clear();
+        self.path.push((start_x, y));
+        for y in (1..height).rev() {
+            let &(x, _) = self.path.last().unwrap();
+            let (parent, _) = self
+                .iter_banded_parent_path_costs(&ranges[y - 1], x, y)
+                .min_by_key(|&(_, path_cost)| path_cost)?;
+            self.path.push(parent);
+        }
+        Some((start_x, y))
+    }
+
+    fn iter_banded_parent_path_costs<'a>(
+        &'a self,
+        range: &'a Range<usize>,
+        x: usize,
+        y: usize,
+    ) -> impl Iterator<Item = ((usize, usize), u32)> + 'a {
+        self.iter_parent_path_costs(x, y)
+            .filter(move |&((px, _), _)| range.contains(&px))
+    }
+
+    /// Counts seams towards the progress of the resize and reports it.
+    fn advance(&mut self, seams: usize) {
+        self.progress.0 += seams;
+        if let Some(observer) = &self.observer {
+            let (done, total) = self.progress;
+            observer.on_seam(done, total);
+        }
+    }
+
+    fn grow_distance(&mut self, distance: usize) -> usize {
+        self.forget_history();
+        let paths = self.get_paths_removed_by_shrink(distance);
+        let distance = paths.len();
+        self.record_seams(SeamKind::Insert, &paths);
 
         for _ in 0..distance {
             self.grid.add_last_column();
         }
 
+        let mut points: Vec<_> = paths.into_iter().flat_map(|(_, path)| path).collect();
+        // Inserting from the right keeps the remaining coordinates valid.
+        points.sort_by_key(|&(x, _)| Reverse(x));
+
         for (x, y) in points {
             let pep = {
                 let left = self.grid.get(x, y);
                 let right = self.grid.get(x + 1, y);
-                left.average(right)
+                let mut pep = left.average(right);
+                if !self.layers.is_empty() {
+                    pep.layer_sample =
+                        interpolate_layers(&self.layers, &mut self.layer_samples, left, right);
+                }
+                pep
             };
             self.add_point(x, y, pep)
         }
+
+        distance
     }
 
-    fn calculate_energy(&mut self) {
-        let mut dirty_points = vec![];
-        mem::swap(&mut dirty_points, &mut self.dirty_points);
+    /// Crops the grid down to the given size around the window holding the
+    /// most energy. Dimensions that would grow are left for carving.
+    fn crop_to(&mut self, width: usize, height: usize) {
+        let width = width.min(self.grid.width());
+        let height = height.min(self.grid.height());
+        if (width, height) == (self.grid.width(), self.grid.height()) {
+            return;
+        }
 
-        for token in dirty_points {
-            self.calculate_pixel_energy_from_token(token);
+        let ((left, top), _) = self.find_crop_window(width, height);
+        let mut rows = vec![vec![]; height];
+        for (x, y, pep) in self.grid.coord_iter() {
+            if (left..left + width).contains(&x) && (top..top + height).contains(&y) {
+                rows[y - top].push(pep.clone());
+            } else {
+                self.removed_energy += pep.energy as u64;
+                self.removed_points.push(pep.original_position);
+            }
+        }
+        self.grid = Grid::new(rows);
+        self.dirty_points.clear();
+        self.forget_history();
+    }
+
+    /// Forgets the removed seams, which can't be put back once the grid
+    /// changes some other way.
+    fn forget_history(&mut self) {
+        if let Some(history) = &mut self.history {
+            *history = History::default();
+        }
+    }
+
+    /// Calculates the energy of every pixel, keyed by where it was in the
+    /// image the carver was created from.
+    fn get_energy_by_original_position(&mut self) -> HashMap<(usize, usize), u32> {
+        self.calculate_all_pixel_energy();
+        self.grid
+            .iter()
+            .map(|pep| (pep.original_position, pep.energy))
+            .collect()
+    }
+
+    fn get_cropped_energy(&mut self, width: usize, height: usize) -> u64 {
+        let width = width.min(self.grid.width());
+        let height = height.min(self.grid.height());
+        let (_, cropped_energy) = self.find_crop_window(width, height);
+        cropped_energy
+    }
+
+    /// Finds the window of the given size that holds the most energy.
+    /// Returns its top left corner along with the energy outside of it.
+    fn find_crop_window(&mut self, width: usize, height: usize) -> ((usize, usize), u64) {
+        self.calculate_all_pixel_energy();
+
+        // A summed area table with a leading row and column of zeroes.
+        let (grid_width, grid_height) = (self.grid.width(), self.grid.height());
+        let stride = grid_width + 1;
+        let mut sums = vec![0u64; stride * (grid_height + 1)];
+        for y in 0..grid_height {
+            for x in 0..grid_width {
+                let energy = self.grid.get(x, y).energy as u64;
+                sums[(y + 1) * stride + x + 1] =
+                    energy + sums[y * stride + x + 1] + sums[(y + 1) * stride + x]
+                        - sums[y * stride + x];
+            }
+        }
+
+        let (bottom, right) = (height * stride, width);
+        let ((left, top), kept) = (0..=grid_height - height)
+            .flat_map(|y| (0..=grid_width - width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let corner = y * stride + x;
+                let sum = sums[corner + bottom + right] + sums[corner]
+                    - sums[corner + right]
+                    - sums[corner + bottom];
+                ((x, y), sum)
+            })
+            .min_by_key(|&(_, sum)| Reverse(sum))
+            .expect("the window should fit in the grid");
+
+        let total = sums[grid_height * stride + grid_width];
+        ((left, top), total - kept)
+    }
+
+    fn apply_seam(&mut self, seam: &Seam) -> Result<()> {
+        let rotated = seam.direction == Direction::Horizontal;
+        if rotated {
+            self.grid.rotate();
         }
+        let result = self.apply_points(seam, rotated);
+        if rotated {
+            self.grid.rotate();
+        }
+        result.with_context(|| format!("can't apply seam {}", seam.index))
+    }
+
+    fn apply_points(&mut self, seam: &Seam, rotated: bool) -> Result<()> {
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let mut points: Vec<_> = seam
+            .points
+            .iter()
+            .map(|&(x, y)| if rotated { (y, x) } else { (x, y) })
+            .collect();
+        points.sort_by_key(|&(_, y)| y);
+        let in_bounds = match seam.kind {
+            SeamKind::Remove => |x: usize, width: usize| x < width,
+            SeamKind::Insert => |x: usize, width: usize| 0 < x && x <= width,
+        };
+        let is_valid = points.len() == height
+            && points
+                .iter()
+                .enumerate()
+                .all(|(row, &(x, y))| row == y && in_bounds(x, width));
+        if !is_valid || (seam.kind == SeamKind::Remove && width == 1) {
+            bail!("the seam doesn't fit a {width}x{height} image");
+        }
+
+        match seam.kind {
+            SeamKind::Remove => {
+                for (x, y) in points {
+                    self.grid.shift_row_left_from_point(x, y);
+                }
+                self.grid.remove_last_column();
+            }
+            SeamKind::Insert => {
+                self.grid.add_last_column();
+                for (x, y) in points {
+                    let pep = self.grid.get(x - 1, y).average(self.grid.get(x, y));
+                    self.grid.shift_row_right_from_point(x - 1, y);
+                    *self.grid.get_mut(x, y) = pep;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks the direction that needs the fewest seams to cover every marked
+    /// pixel, assuming each seam removes one marked pixel per row or column.
+    fn get_removal_direction(&self) -> Direction {
+        let mut rows = vec![0; self.grid.height()];
+        let mut columns = vec![0; self.grid.width()];
+        for (x, y, pep) in self.grid.coord_iter() {
+            if pep.bias < 0 {
+                rows[y] += 1;
+                columns[x] += 1;
+            }
+        }
+        let vertical_seams = rows.into_iter().max().unwrap_or(0);
+        let horizontal_seams = columns.into_iter().max().unwrap_or(0);
+        if horizontal_seams < vertical_seams {
+            Direction::Horizontal
+        } else {
+            Direction::Vertical
+        }
+    }
+
+    /// The bias added to unmarked pixels while removing marked ones. It is
+    /// more than the highest energy in the grid and than any RGB gradient,
+    /// whatever the energy options, so every unmarked pixel costs more than
+    /// every marked one. Path costs saturate on tall images, which ends the
+    /// removal early instead of overflowing.
+    fn get_unmarked_offset(&mut self) -> i32 {
+        self.calculate_all_pixel_energy();
+        let max_energy = self.grid.iter().map(|pep| pep.energy).max().unwrap_or(0);
+        let offset = max_energy.max(MAX_GRADIENT_ENERGY).saturating_add(1);
+        offset.min(i32::MAX as u32) as i32
+    }
+
+    fn offset_unmarked_bias(&mut self, offset: i32) {
+        let removed = self.history.iter_mut().flat_map(|history| {
+            let removals = history.done.iter_mut().chain(&mut history.undone);
+            removals.flat_map(|removal| &mut removal.points)
+        });
+        for pep in self.grid.iter_mut().chain(removed) {
+            if pep.bias >= 0 {
+                pep.bias += offset;
+            }
+        }
+    }
+
+    fn count_marked_points(&self) -> usize {
+        self.grid.iter().filter(|pep| pep.bias < 0).count()
+    }
+
+    /// Checks the average energy per pixel of the seam ending at the given
+    /// point against the hybrid cost limit.
+    fn is_too_costly(&self, x: usize, y: usize) -> bool {
+        let max_seam_cost = match self.hybrid.and_then(|hybrid| hybrid.max_seam_cost) {
+            None => return false,
+            Some(max_seam_cost) => max_seam_cost,
+        };
+        let path_cost = self.grid.get(x, y).path_cost as u64;
+        path_cost > max_seam_cost as u64 * self.grid.height() as u64
+    }
+
+    fn calculate_energy(&mut self) {
+        self.calculate_dirty_pixel_energy();
 
         for y in 0..self.grid.height() {
             for x in 0..self.grid.width() {
@@ -109,6 +1272,15 @@ impl Carver {
         }
     }
 
+    fn calculate_dirty_pixel_energy(&mut self) {
+        let mut dirty_points = vec![];
+        mem::swap(&mut dirty_points, &mut self.dirty_points);
+
+        for token in dirty_points {
+            self.calculate_pixel_energy_from_token(token);
+        }
+    }
+
     fn calculate_all_pixel_energy(&mut self) {
         for y in 0..self.grid.height() {
             for x in 0..self.grid.width() {
@@ -118,20 +1290,28 @@ impl Carver {
     }
 
     fn calculate_pixel_energy(&mut self, x: usize, y: usize) {
-        let energy = {
-            let (left, right, up, down) = self.grid.get_adjacent(x, y);
-            let horizontal_square_gradient = left.square_gradient(right);
-            let vertical_square_gradient = up.square_gradient(down);
-            horizontal_square_gradient + vertical_square_gradient
-        };
-
+        let mut energy = self.energy_options.grid_energy(&self.grid, x, y);
+        if self.layers.iter().any(|slot| slot.energy_weight > 0.0) {
+            let adjacent = self.grid.get_adjacent(x, y);
+            let layer_energy = layer_energy(&self.layers, &self.layer_samples, adjacent);
+            energy = energy.saturating_add(layer_energy);
+        }
         self.grid.get_mut(x, y).energy = energy;
     }
 
+    /// Saliency is estimated once from the whole image up front. It is
+    /// stored with each point so it moves along with the grid afterwards.
+    fn calculate_saliency(&mut self) {
+        let saliency = spectral_residual(&self.rebuild_image());
+        for (x, y, pep) in self.grid.coord_iter_mut() {
+            pep.saliency = saliency[y][x];
+        }
+    }
+
     fn calculate_path_cost(&mut self, x: usize, y: usize) {
         let min_parent_path_cost = self.get_min_parent_path_cost(x, y);
         let energy = self.grid.get(x, y).energy;
-        self.grid.get_mut(x, y).path_cost = min_parent_path_cost + energy;
+        self.grid.get_mut(x, y).path_cost = min_parent_path_cost.saturating_add(energy);
     }
 
     fn get_path_start(&self) -> (usize, usize) {
@@ -158,19 +1338,78 @@ impl Carver {
         }
     }
 
-    fn get_points_removed_by_shrink(&self, distance: usize) -> Vec<(usize, usize)> {
+    /// Finds up to `count` seams that share no pixels using the current path
+    /// costs. Seams are tried from the cheapest bottom row point upwards and
+    /// are dropped if they get boxed in by seams that were already found.
+    fn find_disjoint_paths(&self, count: usize) -> Vec<Vec<(usize, usize)>> {
+        let width = self.grid.width();
+        let y = self.grid.height() - 1;
+        let mut starts: Vec<_> = (0..width).collect();
+        starts.sort_by_key(|&x| self.grid.get(x, y).path_cost);
+
+        let mut used = vec![false; width * self.grid.height()];
+        let mut paths = vec![];
+        for x in starts {
+            if paths.len() == count {
+                break;
+            }
+            if let Some(path) = self.find_unused_path(x, y, &used) {
+                for &(x, y) in &path {
+                    used[y * width + x] = true;
+                }
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    fn find_unused_path(
+        &self,
+        start_x: usize,
+        start_y: usize,
+        used: &[bool],
+    ) -> Option<Vec<(usize, usize)>> {
+        let width = self.grid.width();
+        if used[start_y * width + start_x] {
+            return None;
+        }
+        let mut path = vec![(start_x, start_y)];
+        loop {
+            let &(x, y) = path.last().unwrap();
+            if y == 0 {
+                return Some(path);
+            }
+            let parent = self
+                .iter_parent_path_costs(x, y)
+                .filter(|&((x, y), _)| !used[y * width + x])
+                .min_by_key(|&(_, path_cost)| path_cost)
+                .map(|(coord, _)| coord)?;
+            path.push(parent);
+        }
+    }
+
+    /// Finds the seams shrinking would remove along with their costs, in the
+    /// grid's current coordinates.
+    ///
+    /// The shrinking reports its seams as the progress of the resize, since
+    /// finding them is most of the work of growing.
+    fn get_paths_removed_by_shrink(&mut self, distance: usize) -> Vec<(u32, Vec<(usize, usize)>)> {
         let mut shrinker = self.clone();
 
         shrinker.removed_points.clear();
+        shrinker.seams.clear();
+        shrinker.history = None;
         shrinker.reset_positions();
 
         shrinker.shrink_distance(distance);
-        let mut points = shrinker.get_removed_points().to_vec();
-
-        // Reverse sort by x values
-        points.sort_by(|a, b| b.0.cmp(&a.0));
-
-        points
+        self.progress = shrinker.progress;
+        let height = shrinker.grid.height();
+        shrinker
+            .seams
+            .iter()
+            .zip(shrinker.removed_points.chunks(height))
+            .map(|(seam, points)| (seam.cost, points.to_vec()))
+            .collect()
     }
 
     fn reset_positions(&mut self) {
@@ -181,36 +1420,44 @@ impl Carver {
     }
 
     fn calculate_pixel_energy_from_token(&mut self, token: Token) {
-        let energy = match self.grid.get_token_adjacent(&token) {
-            None => return,
-            Some((left, right, up, down)) => {
-                let horizontal_square_gradient = left.square_gradient(right);
-                let vertical_square_gradient = up.square_gradient(down);
-                horizontal_square_gradient + vertical_square_gradient
-            }
-        };
-
-        self.grid
-            .trade_mut(token)
-            .expect("token should still be valid")
-            .energy = energy;
+        if let Some((x, y)) = self.grid.get_token_position(&token) {
+            self.calculate_pixel_energy(x, y);
+        }
     }
 
     fn get_min_parent_path_cost(&self, x: usize, y: usize) -> u32 {
-        self.grid
-            .iter_parents(x, y)
-            .map(|pep| pep.path_cost)
+        self.iter_parent_path_costs(x, y)
+            .map(|(_, path_cost)| path_cost)
             .min()
             .unwrap_or(0)
     }
 
     fn get_parent_with_min_path_cost(&self, x: usize, y: usize) -> Option<(usize, usize)> {
-        self.grid
-            .iter_parents_with_coords(x, y)
-            .min_by_key(|&((_, _), pep)| pep.path_cost)
+        self.iter_parent_path_costs(x, y)
+            .min_by_key(|&(_, path_cost)| path_cost)
             .map(|(coord, _)| coord)
     }
 
+    /// Yields the parents a seam may reach from the given point along with
+    /// their path cost, including any penalty for drifting sideways.
+    fn iter_parent_path_costs(
+        &self,
+        x: usize,
+        y: usize,
+    ) -> impl Iterator<Item = ((usize, usize), u32)> + '_ {
+        let Stiffness {
+            max_drift,
+            diagonal_penalty,
+        } = self.stiffness;
+        self.grid
+            .iter_parents_within(x, y, max_drift)
+            .map(move |(coord, pep)| {
+                let drift = coord.0.abs_diff(x) as u32;
+                let penalty = drift.saturating_mul(diagonal_penalty);
+                (coord, pep.path_cost.saturating_add(penalty))
+            })
+    }
+
     fn add_point(&mut self, x: usize, y: usize, pep: PixelEnergyPoint) {
         self.removed_points
             .push(self.grid.get(x, y).original_position);
@@ -219,17 +1466,189 @@ impl Carver {
     }
 
     fn remove_path(&mut self) {
-        for &(x, y) in &self.path {
-            let adjacent_tokens = self.grid.make_adjacent_tokens(x, y);
-            self.dirty_points.extend_from_slice(&adjacent_tokens);
+        let path = mem::take(&mut self.path);
+        let (start_x, start_y) = path[0];
+        let cost = self.grid.get(start_x, start_y).path_cost;
+        self.record_seams(SeamKind::Remove, &[(cost, path.clone())]);
+        self.record_removals(std::slice::from_ref(&path));
+        self.remove_points(&path);
+        self.path = path;
+    }
+
+    /// Removes one point from every row and the column left over at the end.
+    fn remove_points(&mut self, path: &[(usize, usize)]) {
+        for &(x, y) in path {
+            self.mark_dirty(x, y);
 
-            let original_position = self.grid.get(x, y).original_position;
-            self.removed_points.push(original_position);
+            let pep = self.grid.get(x, y);
+            self.removed_energy += pep.energy as u64;
+            self.removed_points.push(pep.original_position);
             self.grid.shift_row_left_from_point(x, y);
         }
         self.grid.remove_last_column();
     }
 
+    /// Keeps the points of paths about to be removed in the history, along
+    /// with the seams just recorded for them.
+    fn record_removals(&mut self, paths: &[Vec<(usize, usize)>]) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        let first = self.seams.len() - paths.len();
+        for (seam, path) in self.seams[first..].iter().zip(paths) {
+            let mut path = path.clone();
+            path.sort_by_key(|&(_, y)| y);
+            let points = path
+                .iter()
+                .map(|&(x, y)| self.grid.get(x, y).clone())
+                .collect();
+            history.done.push(Removal {
+                seam: seam.clone(),
+                points,
+            });
+        }
+        history.undone.clear();
+    }
+
+    /// Puts the points of a removed seam back where they were and takes the
+    /// seam off the record.
+    fn restore_removal(&mut self, removal: &Removal) {
+        let rotated = removal.seam.direction == Direction::Horizontal;
+        if rotated {
+            self.grid.rotate();
+        }
+        let points = get_grid_points(&removal.seam, rotated);
+        for (&(x, y), pep) in points.iter().zip(&removal.points) {
+            self.removed_energy -= pep.energy as u64;
+            self.grid.insert_point(x, y, pep.clone());
+        }
+        for &(x, y) in &points {
+            self.mark_dirty(x, y);
+        }
+        if rotated {
+            self.grid.rotate();
+        }
+        self.seams.pop();
+        let removed = self.removed_points.len() - points.len();
+        self.removed_points.truncate(removed);
+    }
+
+    /// Removes an undone seam again.
+    fn repeat_removal(&mut self, seam: Seam) {
+        let rotated = seam.direction == Direction::Horizontal;
+        if rotated {
+            self.grid.rotate();
+        }
+        let points = get_grid_points(&seam, rotated);
+        let removal = Removal {
+            points: points
+                .iter()
+                .map(|&(x, y)| self.grid.get(x, y).clone())
+                .collect(),
+            seam,
+        };
+        self.remove_points(&points);
+        if rotated {
+            self.grid.rotate();
+        }
+        self.seams.push(removal.seam.clone());
+        if let Some(history) = &mut self.history {
+            history.done.push(removal);
+        }
+    }
+
+    /// Marks the points whose energy depends on the given point so that it
+    /// gets recalculated before the next seam is found.
+    fn mark_dirty(&mut self, x: usize, y: usize) {
+        match self.energy_options.function.reach() {
+            1 => {
+                let adjacent_tokens = self.grid.make_adjacent_tokens(x, y);
+                self.dirty_points.extend_from_slice(&adjacent_tokens);
+            }
+            reach => {
+                let window_tokens = self.grid.make_window_tokens(x, y, reach);
+                self.dirty_points.extend(window_tokens);
+            }
+        }
+    }
+
+    fn remove_paths(&mut self, paths: Vec<Vec<(usize, usize)>>) {
+        let costed: Vec<_> = paths
+            .iter()
+            .map(|path| (self.grid.get(path[0].0, path[0].1).path_cost, path.clone()))
+            .collect();
+        self.record_seams(SeamKind::Remove, &costed);
+        self.record_removals(&paths);
+
+        let mut rows = vec![vec![]; self.grid.height()];
+        for path in &paths {
+            for &(x, y) in path {
+                self.mark_dirty(x, y);
+
+                let pep = self.grid.get(x, y);
+                self.removed_energy += pep.energy as u64;
+                self.removed_points.push(pep.original_position);
+                rows[y].push(x);
+            }
+        }
+
+        for (y, mut row) in rows.into_iter().enumerate() {
+            // Shifting from the right keeps the remaining coordinates valid.
+            row.sort_by_key(|&x| Reverse(x));
+            for x in row {
+                self.grid.shift_row_left_from_point(x, y);
+            }
+        }
+
+        for _ in &paths {
+            self.grid.remove_last_column();
+        }
+    }
+
+    /// Records seams that are carved together as though they were carved one
+    /// after another, so that they can be applied in order. The paths are in
+    /// grid coordinates from before any of them are carved, and inserted
+    /// paths give the point each new pixel goes to the right of.
+    fn record_seams(&mut self, kind: SeamKind, paths: &[(u32, Vec<(usize, usize)>)]) {
+        let rotated = self.grid.is_rotated();
+        let direction = if rotated {
+            Direction::Horizontal
+        } else {
+            Direction::Vertical
+        };
+
+        // The columns already carved in each row by earlier paths.
+        let mut carved: Vec<Vec<usize>> = vec![vec![]; self.grid.height()];
+        for (cost, path) in paths {
+            let mut path = path.clone();
+            path.sort_by_key(|&(_, y)| y);
+            let points = path
+                .into_iter()
+                .map(|(x, y)| {
+                    let row = &mut carved[y];
+                    let before = row.partition_point(|&other| other < x);
+                    row.insert(before, x);
+                    let x = match kind {
+                        SeamKind::Remove => x - before,
+                        SeamKind::Insert => x + before + 1,
+                    };
+                    if rotated {
+                        (y, x)
+                    } else {
+                        (x, y)
+                    }
+                })
+                .collect();
+            self.seams.push(Seam {
+                direction,
+                kind,
+                index: self.seams.len(),
+                cost: *cost,
+                points,
+            });
+        }
+    }
+
     fn rebuild_image(&self) -> DynamicImage {
         let mut image =
             DynamicImage::new_rgba8(self.grid.width() as u32, self.grid.height() as u32);
@@ -239,7 +1658,6 @@ impl Carver {
         image
     }
 
-    #[cfg(test)]
     fn get_pixel_energy(&self) -> Vec<Vec<u32>> {
         let mut grid = vec![];
         for y in 0..self.grid.height() {
@@ -252,7 +1670,6 @@ impl Carver {
         grid
     }
 
-    #[cfg(test)]
     fn get_path_energy(&self) -> Vec<Vec<u32>> {
         let mut grid = vec![];
         for y in 0..self.grid.height() {
@@ -266,9 +1683,68 @@ impl Carver {
     }
 }
 
+/// Removes one seam each time it is advanced. See [`Carver::seams`].
+pub struct Seams<'a> {
+    carver: &'a mut Carver,
+    direction: Direction,
+}
+
+impl Seams<'_> {
+    /// The image as carved so far.
+    pub fn image(&mut self) -> DynamicImage {
+        // The grid is transposed while horizontal seams are removed.
+        if self.direction == Direction::Horizontal {
+            self.carver.grid.rotate();
+        }
+        let image = self.carver.rebuild_image();
+        if self.direction == Direction::Horizontal {
+            self.carver.grid.rotate();
+        }
+        image
+    }
+
+    /// The size of the image as carved so far.
+    pub fn size(&self) -> (usize, usize) {
+        let (width, height) = self.carver.get_size();
+        match self.direction {
+            Direction::Vertical => (width, height),
+            Direction::Horizontal => (height, width),
+        }
+    }
+}
+
+impl Iterator for Seams<'_> {
+    type Item = Seam;
+
+    fn next(&mut self) -> Option<Seam> {
+        if self.carver.grid.width() <= 1 || self.carver.shrink_distance(1) == 0 {
+            return None;
+        }
+        self.carver.seams.last().cloned()
+    }
+}
+
+impl Drop for Seams<'_> {
+    fn drop(&mut self) {
+        if self.direction == Direction::Horizontal {
+            self.carver.grid.rotate();
+        }
+        self.carver.output_size = self.carver.get_size();
+    }
+}
+
+/// The points of a seam in the coordinates of the grid, rotated or not, in
+/// the order of the seam's points.
+fn get_grid_points(seam: &Seam, rotated: bool) -> Vec<(usize, usize)> {
+    seam.points
+        .iter()
+        .map(|&(x, y)| if rotated { (y, x) } else { (x, y) })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Carver;
+    use super::{Carver, Stiffness};
     use image;
 
     macro_rules! setup_carver {
@@ -337,6 +1813,33 @@ mod tests {
         assert_eq!(get_medium_path(), carver.path);
     }
 
+    #[test]
+    fn carver_medium_straight_path_test() {
+        let input = image::load_from_memory(MEDIUM).unwrap();
+        let mut carver = Carver::new(&input).with_stiffness(Stiffness {
+            max_drift: 0,
+            diagonal_penalty: 0,
+        });
+        carver.calculate_all_pixel_energy();
+        carver.calculate_energy();
+        let (x, y) = carver.get_path_start();
+        carver.find_path(x, y);
+        assert_eq!(vec![(0, 4), (0, 3), (0, 2), (0, 1), (0, 0)], carver.path);
+    }
+
+    #[test]
+    fn carver_medium_find_disjoint_paths_test() {
+        let carver = setup_carver!(MEDIUM);
+        let paths = carver.find_disjoint_paths(3);
+        assert_eq!(3, paths.len());
+        assert_eq!(get_medium_path(), paths[0]);
+        for (i, path) in paths.iter().enumerate() {
+            for other in &paths[i + 1..] {
+                assert!(path.iter().all(|point| !other.contains(point)));
+            }
+        }
+    }
+
     static SMALL: &[u8] = include_bytes!("../tests/images/small_energy.png");
     static MEDIUM: &[u8] = include_bytes!("../tests/images/medium_energy.png");
 
@@ -43,6 +43,7 @@ impl FromStr for Change {
     type Err = Error;
 
     /// Parses the given string into a Change.
+    #[allow(clippy::needless_return)]
     fn from_str(s: &str) -> Result<Self> {
         if s.contains("..") {
             // This is a range of changes of the forms:
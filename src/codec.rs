@@ -0,0 +1,266 @@
+use std::io::{self, BufWriter, Cursor, Read, Seek, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{self, CompressionType, PngDecoder, PngEncoder};
+use image::codecs::webp::{WebPDecoder, WebPEncoder};
+use image::{
+    AnimationDecoder, DynamicImage, Frame, ImageDecoder, ImageFormat, ImageReader, Limits, Rgb,
+    RgbImage,
+};
+
+/// The path that stands for stdin when reading and stdout when writing.
+pub const STDIO: &str = "-";
+
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO)
+}
+
+/// An image read from a file, which may be animated.
+pub enum Input {
+    Image(DynamicImage),
+    /// Every frame of an animation, each the full size of the animation.
+    Animation(Vec<Frame>),
+}
+
+/// Reads an image from a file or from stdin, guessing its format from its
+/// contents. The format is returned so it can be reused for the output.
+///
+/// Decoding may use up to `max_memory` bytes when that is more than the
+/// default limit of half a gigabyte, which would rule out the very large
+/// scans that need carving with `--max-memory`.
+pub fn read_image(
+    path: &Path,
+    max_memory: Option<usize>,
+) -> Result<(DynamicImage, Option<ImageFormat>)> {
+    let mut reader = ImageReader::new(Cursor::new(read_bytes(path)?)).with_guessed_format()?;
+    reader.limits(decode_limits(max_memory));
+    let format = reader.format();
+    let image = reader.decode().context("decoding image")?;
+    Ok((image, format))
+}
+
+/// Reads an image like [`read_image`], but decodes every frame of animated
+/// GIF, PNG and WebP images instead of only the first.
+pub fn read_input(path: &Path, max_memory: Option<usize>) -> Result<(Input, Option<ImageFormat>)> {
+    let limits = decode_limits(max_memory);
+    let mut reader = ImageReader::new(Cursor::new(read_bytes(path)?)).with_guessed_format()?;
+    reader.limits(limits.clone());
+    let format = reader.format();
+    let frames = match format {
+        Some(ImageFormat::Gif) => {
+            let mut decoder = GifDecoder::new(reader.into_inner())?;
+            decoder.set_limits(limits)?;
+            decoder.into_frames().collect_frames()?
+        }
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::with_limits(reader.into_inner(), limits)?;
+            if !decoder.is_apng()? {
+                return Ok((Input::Image(DynamicImage::from_decoder(decoder)?), format));
+            }
+            decoder.apng()?.into_frames().collect_frames()?
+        }
+        Some(ImageFormat::WebP) => {
+            let mut decoder = WebPDecoder::new(reader.into_inner())?;
+            decoder.set_limits(limits)?;
+            if !decoder.has_animation() {
+                return Ok((Input::Image(DynamicImage::from_decoder(decoder)?), format));
+            }
+            decoder.into_frames().collect_frames()?
+        }
+        _ => return Ok((Input::Image(reader.decode()?), format)),
+    };
+    let input = match <[Frame; 1]>::try_from(frames) {
+        // A single frame isn't worth carrying the animation for.
+        Ok([frame]) => Input::Image(DynamicImage::ImageRgba8(frame.into_buffer())),
+        Err(frames) => Input::Animation(frames),
+    };
+    Ok((input, format))
+}
+
+/// Raises the default decoding limits to `max_memory` bytes, but never
+/// lowers them.
+fn decode_limits(max_memory: Option<usize>) -> Limits {
+    let mut limits = Limits::default();
+    if let Some(max_memory) = max_memory {
+        let max_alloc = limits
+            .max_alloc
+            .map(|max_alloc| max_alloc.max(max_memory as u64));
+        limits.max_alloc = max_alloc;
+    }
+    limits
+}
+
+fn read_bytes(path: &Path) -> Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut bytes = vec![];
+        io::stdin()
+            .lock()
+            .read_to_end(&mut bytes)
+            .context("reading stdin")?;
+        Ok(bytes)
+    } else {
+        std::fs::read(path).with_context(|| format!("reading {path:?}"))
+    }
+}
+
+/// Settings for encoding an image beyond its format.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EncodeOptions {
+    /// The quality from 1 to 100 for lossy formats, or the encoder's default.
+    /// Only JPEG is written lossily; WebP is always lossless and refuses a
+    /// quality below 100.
+    pub quality: Option<u8>,
+    pub png_compression: CompressionType,
+    /// The colour transparent pixels are blended against. Formats without
+    /// an alpha channel use white when this is not set.
+    pub background: Option<Rgb<u8>>,
+}
+
+impl EncodeOptions {
+    /// Encodes an image in the given format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate image;
+    /// # extern crate rmr;
+    /// # use std::io::Cursor;
+    /// # use image::{DynamicImage, GenericImageView, ImageFormat, Rgb};
+    /// # use rmr::codec::EncodeOptions;
+    /// # fn main() {
+    /// let image = DynamicImage::new_rgba8(4, 4);
+    /// let options = EncodeOptions {
+    ///     quality: Some(80),
+    ///     background: Some(Rgb([255, 0, 0])),
+    ///     ..EncodeOptions::default()
+    /// };
+    /// let mut bytes = Cursor::new(vec![]);
+    /// options.encode(&image, &mut bytes, ImageFormat::Jpeg).unwrap();
+    ///
+    /// let decoded = image::load_from_memory(bytes.get_ref()).unwrap();
+    /// let [r, g, b, _] = decoded.get_pixel(1, 1).0;
+    /// assert!(r > 240 && g < 16 && b < 16);
+    /// # }
+    /// ```
+    pub fn encode<W: Write + Seek>(
+        &self,
+        image: &DynamicImage,
+        writer: &mut W,
+        format: ImageFormat,
+    ) -> Result<()> {
+        let has_alpha = image.color().has_alpha();
+        let flattened;
+        let image = match (self.background, format) {
+            (Some(background), _) if has_alpha => {
+                flattened = flatten(image, background);
+                &flattened
+            }
+            (None, ImageFormat::Jpeg) if has_alpha => {
+                flattened = flatten(image, Rgb([255, 255, 255]));
+                &flattened
+            }
+            _ => image,
+        };
+
+        match format {
+            ImageFormat::Jpeg => {
+                let encoder = match self.quality {
+                    Some(quality) => JpegEncoder::new_with_quality(writer, quality.clamp(1, 100)),
+                    None => JpegEncoder::new(writer),
+                };
+                // JPEG has neither alpha nor more than eight bits per channel.
+                DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)?;
+            }
+            ImageFormat::Png => {
+                let encoder = PngEncoder::new_with_quality(
+                    writer,
+                    self.png_compression,
+                    png::FilterType::default(),
+                );
+                image.write_with_encoder(encoder)?;
+            }
+            ImageFormat::WebP => {
+                if self.quality.is_some_and(|quality| quality < 100) {
+                    bail!("WebP can only be written losslessly, drop --quality or use 100");
+                }
+                image.write_with_encoder(WebPEncoder::new_lossless(writer))?;
+            }
+            _ => image.write_to(writer, format)?,
+        }
+        Ok(())
+    }
+}
+
+/// Writes an image to a file or to stdout in the given format.
+pub fn write_image(
+    image: &DynamicImage,
+    path: &Path,
+    format: ImageFormat,
+    options: &EncodeOptions,
+) -> Result<()> {
+    if !is_stdio(path) {
+        let file = std::fs::File::create(path).with_context(|| format!("creating {path:?}"))?;
+        let mut writer = BufWriter::new(file);
+        options.encode(image, &mut writer, format)?;
+        writer.flush()?;
+        return Ok(());
+    }
+    // Encoders may need to seek, which stdout can't do.
+    let mut bytes = Cursor::new(vec![]);
+    options.encode(image, &mut bytes, format)?;
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(bytes.get_ref())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Checks that animations can be written in a format, which only GIF can.
+pub fn check_animation_format(format: ImageFormat) -> Result<()> {
+    if format != ImageFormat::Gif {
+        bail!("animations can only be written as GIF, not {format:?}; use --format gif");
+    }
+    Ok(())
+}
+
+/// Writes the frames of an animation to a file or to stdout. Animations can
+/// only be encoded as GIF, which loops forever.
+pub fn write_animation(frames: Vec<Frame>, path: &Path, format: ImageFormat) -> Result<()> {
+    check_animation_format(format)?;
+    let encode = |writer: &mut dyn Write| -> Result<()> {
+        let mut encoder = GifEncoder::new(writer);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)?;
+        Ok(())
+    };
+    if !is_stdio(path) {
+        let file = std::fs::File::create(path).with_context(|| format!("creating {path:?}"))?;
+        let mut writer = BufWriter::new(file);
+        encode(&mut writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+    let mut stdout = io::stdout().lock();
+    encode(&mut stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Blends an image over a solid background, dropping its alpha channel.
+fn flatten(image: &DynamicImage, background: Rgb<u8>) -> DynamicImage {
+    let image = image.to_rgba8();
+    let flattened = RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let alpha = a as u32;
+        let blend =
+            |c: u8, bg: u8| ((c as u32 * alpha + bg as u32 * (255 - alpha) + 127) / 255) as u8;
+        Rgb([
+            blend(r, background.0[0]),
+            blend(g, background.0[1]),
+            blend(b, background.0[2]),
+        ])
+    });
+    DynamicImage::ImageRgb8(flattened)
+}
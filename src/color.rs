@@ -0,0 +1,178 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Error, Result};
+
+/// The colour space pixel gradients are measured in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Squared differences of the raw sRGB channels.
+    #[default]
+    Rgb,
+    /// Euclidean distance in CIELAB.
+    Lab76,
+    /// The CIEDE2000 colour difference.
+    Lab2000,
+    /// Differences in lightness only.
+    Luminance,
+}
+
+impl FromStr for ColorSpace {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rgb" => Ok(ColorSpace::Rgb),
+            "lab76" => Ok(ColorSpace::Lab76),
+            "lab2000" => Ok(ColorSpace::Lab2000),
+            "luminance" => Ok(ColorSpace::Luminance),
+            _ => bail!("unknown color space: {s}"),
+        }
+    }
+}
+
+/// A colour in CIELAB as `[L, a, b]`.
+pub type Lab = [f32; 3];
+
+/// Converts an sRGB colour to CIELAB using a D65 white point.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate rmr;
+/// # use rmr::color::srgb_to_lab;
+/// # fn main() {
+/// let [l, a, b] = srgb_to_lab([255, 255, 255]);
+/// assert!((l - 100.0).abs() < 0.01);
+/// assert!(a.abs() < 0.01 && b.abs() < 0.01);
+///
+/// let [l, _, _] = srgb_to_lab([0, 0, 0]);
+/// assert_eq!(0.0, l);
+/// # }
+/// ```
+pub fn srgb_to_lab(rgb: [u8; 3]) -> Lab {
+    let linear = linear_table();
+    let [r, g, b] = rgb.map(|c| linear[c as usize]);
+
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.072175 * b;
+    let z = (0.0193339 * r + 0.119192 * g + 0.9503041 * b) / 1.08883;
+
+    let (fx, fy, fz) = (lab_f(x), lab_f(y), lab_f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// The CIE76 colour difference, which is the distance between two colours.
+pub fn delta_e76(lab1: &Lab, lab2: &Lab) -> f32 {
+    let [dl, da, db] = [lab1[0] - lab2[0], lab1[1] - lab2[1], lab1[2] - lab2[2]];
+    (dl * dl + da * da + db * db).sqrt()
+}
+
+/// The CIEDE2000 colour difference.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate rmr;
+/// # use rmr::color::delta_e2000;
+/// # fn main() {
+/// let result = delta_e2000(&[50.0, 2.6772, -79.7751], &[50.0, 0.0, -82.7485]);
+/// assert!((result - 2.0425).abs() < 0.001);
+/// # }
+/// ```
+pub fn delta_e2000(lab1: &Lab, lab2: &Lab) -> f32 {
+    let &[l1, a1, b1] = lab1;
+    let &[l2, a2, b2] = lab2;
+
+    let c_bar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+    let g = 0.5 * (1.0 - chroma_weight(c_bar));
+    let (a1, a2) = ((1.0 + g) * a1, (1.0 + g) * a2);
+    let (c1, c2) = ((a1 * a1 + b1 * b1).sqrt(), (a2 * a2 + b2 * b2).sqrt());
+    let (h1, h2) = (hue_angle(a1, b1), hue_angle(a2, b2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * chroma_weight(c_bar);
+    let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar;
+    let s_h = 1.0 + 0.015 * c_bar * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).max(0.0).sqrt()
+}
+
+/// `sqrt(c^7 / (c^7 + 25^7))`, which pulls the weighting of neutral colours
+/// towards zero.
+fn chroma_weight(c: f32) -> f32 {
+    let c7 = c.powi(7);
+    (c7 / (c7 + 25f32.powi(7))).sqrt()
+}
+
+/// The hue angle in degrees from 0 to 360.
+fn hue_angle(a: f32, b: f32) -> f32 {
+    if a == 0.0 && b == 0.0 {
+        return 0.0;
+    }
+    let h = b.atan2(a) * 180.0 / PI;
+    if h < 0.0 {
+        h + 360.0
+    } else {
+        h
+    }
+}
+
+fn lab_f(t: f32) -> f32 {
+    const DELTA: f32 = 6.0 / 29.0;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+/// Maps each sRGB channel value to its linear intensity.
+fn linear_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0.0; 256];
+        for (i, value) in table.iter_mut().enumerate() {
+            let c = i as f32 / 255.0;
+            *value = if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+        }
+        table
+    })
+}
@@ -0,0 +1,277 @@
+use std::cmp::Reverse;
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::carve::Stiffness;
+
+const CHANNELS: usize = 4;
+
+/// A carver for images too large for [`Carver`](crate::carve::Carver). It
+/// keeps only the RGBA bytes of each pixel, works out energy from them when
+/// it is needed and keeps path costs for a few rows at a time, so it needs a
+/// few bytes per pixel rather than dozens.
+///
+/// Energy is the plain RGBA gradient and seams are removed one at a time, so
+/// none of the other energy options apply.
+#[derive(Clone, Debug)]
+pub struct CompactCarver {
+    /// The pixels row by row, four bytes each.
+    pixels: Vec<u8>,
+    width: usize,
+    height: usize,
+    stiffness: Stiffness,
+}
+
+impl CompactCarver {
+    pub fn new(image: &DynamicImage) -> Self {
+        Self {
+            pixels: image.to_rgba8().into_raw(),
+            width: image.width() as usize,
+            height: image.height() as usize,
+            stiffness: Stiffness::default(),
+        }
+    }
+
+    pub fn with_stiffness(mut self, stiffness: Stiffness) -> Self {
+        self.stiffness = stiffness;
+        self
+    }
+
+    /// Carves the image to the given size. The carver is used up so that its
+    /// pixels become the output without being copied.
+    pub fn resize(mut self, width: usize, height: usize) -> DynamicImage {
+        self.carve_width_to(width);
+        if height != self.height {
+            self.transpose();
+            self.carve_width_to(height);
+            self.transpose();
+        }
+        let (width, height) = (self.width as u32, self.height as u32);
+        let image = RgbaImage::from_raw(width, height, self.pixels).expect("the carver's own size");
+        DynamicImage::ImageRgba8(image)
+    }
+
+    /// Estimates the most memory in bytes carving between the given sizes
+    /// needs, not counting the decoded input image.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rmr;
+    /// # use rmr::compact::CompactCarver;
+    /// # fn main() {
+    /// let bytes = CompactCarver::estimate_memory((20_000, 20_000), (15_000, 20_000));
+    /// assert!(bytes < 4 << 30);
+    /// # }
+    /// ```
+    pub fn estimate_memory(
+        (width, height): (usize, usize),
+        (target_width, target_height): (usize, usize),
+    ) -> usize {
+        let largest = (width.max(target_width), height.max(target_height));
+        // The pixels, and the copy made while transposing.
+        let mut bytes = 2 * largest.0 * largest.1 * CHANNELS;
+        if target_width > width || target_height > height {
+            // Growing finds its seams on a copy that tracks where each pixel
+            // came from.
+            bytes += width * height * (CHANNELS + 4);
+        }
+        bytes + path_cost_memory(largest).max(path_cost_memory((largest.1, largest.0)))
+    }
+
+    fn carve_width_to(&mut self, target: usize) {
+        if target < self.width {
+            for _ in target..self.width {
+                let seam = self.find_seam();
+                remove_seam(&mut self.pixels, self.width, CHANNELS, &seam);
+                self.width -= 1;
+            }
+        }
+        while self.width < target {
+            let distance = (target - self.width).min(self.width);
+            self.grow(distance);
+        }
+    }
+
+    /// Inserts `distance` seams next to the ones shrinking would remove,
+    /// averaging each new pixel from its neighbours.
+    fn grow(&mut self, distance: usize) {
+        let (width, height) = (self.width, self.height);
+        let mut shrinker = self.clone();
+        let mut columns: Vec<u32> = (0..height).flat_map(|_| 0..width as u32).collect();
+        let mut inserted = vec![vec![]; height];
+        for _ in 0..distance {
+            let seam = shrinker.find_seam();
+            for (y, &x) in seam.iter().enumerate() {
+                inserted[y].push(columns[y * shrinker.width + x] as usize);
+            }
+            remove_seam(&mut columns, shrinker.width, 1, &seam);
+            remove_seam(&mut shrinker.pixels, shrinker.width, CHANNELS, &seam);
+            shrinker.width -= 1;
+        }
+        drop((shrinker, columns));
+
+        let mut pixels = Vec::with_capacity((width + distance) * height * CHANNELS);
+        for (y, mut row) in inserted.into_iter().enumerate() {
+            row.sort_by_key(|&x| Reverse(x));
+            for x in 0..width {
+                let pixel = self.pixel(x, y);
+                pixels.extend_from_slice(pixel);
+                if row.last() == Some(&x) {
+                    row.pop();
+                    let right = self.pixel((x + 1).min(width - 1), y);
+                    pixels.extend(
+                        pixel
+                            .iter()
+                            .zip(right)
+                            .map(|(&a, &b)| ((a as u16 + b as u16) / 2) as u8),
+                    );
+                }
+            }
+        }
+        self.pixels = pixels;
+        self.width += distance;
+    }
+
+    /// Finds the cheapest vertical seam, returning its column in each row.
+    ///
+    /// Only every `step`th row of path costs is kept on the way down. On the
+    /// way back up the rows between two of those are worked out again, one
+    /// strip at a time.
+    fn find_seam(&self) -> Vec<usize> {
+        let step = strip_height(self.height);
+        let mut checkpoints = vec![];
+        let mut row = vec![];
+        let mut previous: Option<Vec<u32>> = None;
+        for y in 0..self.height {
+            self.path_cost_row(y, previous.as_deref(), &mut row);
+            if (y + 1) % step == 0 && y + 1 < self.height {
+                checkpoints.push(row.clone());
+            }
+            previous = Some(std::mem::replace(&mut row, previous.unwrap_or_default()));
+        }
+        let last = previous.expect("an image with rows");
+
+        let mut seam = vec![0; self.height];
+        let mut x = (0..self.width)
+            .min_by_key(|&x| last[x])
+            .expect("an image with columns");
+        seam[self.height - 1] = x;
+        let mut strip = vec![];
+        for start in (0..self.height).step_by(step).rev() {
+            let end = (start + step).min(self.height);
+            let checkpoint = start.checked_sub(1).map(|_| &checkpoints[start / step - 1]);
+            strip.clear();
+            for y in start..end {
+                let mut row = vec![];
+                self.path_cost_row(y, strip.last().or(checkpoint).map(Vec::as_slice), &mut row);
+                strip.push(row);
+            }
+            for y in (start + 1..end).rev() {
+                x = self.cheapest_parent(&strip[y - 1 - start], x);
+                seam[y - 1] = x;
+            }
+            if let Some(checkpoint) = checkpoint {
+                x = self.cheapest_parent(checkpoint, x);
+                seam[start - 1] = x;
+            }
+        }
+        seam
+    }
+
+    /// Works out the path costs of a row from those of the row above.
+    fn path_cost_row(&self, y: usize, previous: Option<&[u32]>, row: &mut Vec<u32>) {
+        row.clear();
+        row.extend((0..self.width).map(|x| {
+            let energy = self.energy(x, y);
+            match previous {
+                None => energy,
+                Some(previous) => {
+                    let parent = self.cheapest_parent(previous, x);
+                    self.parent_cost(previous, parent, x).saturating_add(energy)
+                }
+            }
+        }));
+    }
+
+    /// Picks the parent a seam through `x` comes from, preferring the
+    /// leftmost on ties like [`Carver`](crate::carve::Carver) does.
+    fn cheapest_parent(&self, previous: &[u32], x: usize) -> usize {
+        let max_drift = self.stiffness.max_drift;
+        let lo = x.saturating_sub(max_drift);
+        let hi = (x + max_drift + 1).min(self.width);
+        (lo..hi)
+            .min_by_key(|&parent| self.parent_cost(previous, parent, x))
+            .expect("a parent within reach")
+    }
+
+    fn parent_cost(&self, previous: &[u32], parent: usize, x: usize) -> u32 {
+        let drift = parent.abs_diff(x) as u32;
+        previous[parent].saturating_add(drift.saturating_mul(self.stiffness.diagonal_penalty))
+    }
+
+    /// The squared gradient to the adjacent pixels, wrapping around the
+    /// edges like the default energy of [`Carver`](crate::carve::Carver).
+    fn energy(&self, x: usize, y: usize) -> u32 {
+        let (width, height) = (self.width, self.height);
+        let left = self.pixel((x + width - 1) % width, y);
+        let right = self.pixel((x + 1) % width, y);
+        let up = self.pixel(x, (y + height - 1) % height);
+        let down = self.pixel(x, (y + 1) % height);
+        square_gradient(left, right) + square_gradient(up, down)
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> &[u8] {
+        let start = (y * self.width + x) * CHANNELS;
+        &self.pixels[start..start + CHANNELS]
+    }
+
+    fn transpose(&mut self) {
+        let (width, height) = (self.width, self.height);
+        let mut pixels = vec![0; self.pixels.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let to = (x * height + y) * CHANNELS;
+                pixels[to..to + CHANNELS].copy_from_slice(self.pixel(x, y));
+            }
+        }
+        self.pixels = pixels;
+        (self.width, self.height) = (height, width);
+    }
+}
+
+/// How many rows of path costs are worked out between kept rows, which
+/// keeps about twice the square root of the height in memory.
+fn strip_height(height: usize) -> usize {
+    ((height as f64).sqrt().ceil() as usize).max(1)
+}
+
+fn path_cost_memory((width, height): (usize, usize)) -> usize {
+    let step = strip_height(height);
+    (height / step + step + 2) * width * 4
+}
+
+fn square_gradient(a: &[u8], b: &[u8]) -> u32 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, &b)| (a as i32 - b as i32).pow(2) as u32)
+        .sum()
+}
+
+/// Removes one value per row from rows of `width` values of `channels`
+/// each, shifting the rest of the data down in place.
+fn remove_seam<T: Copy>(data: &mut Vec<T>, width: usize, channels: usize, seam: &[usize]) {
+    let mut write = 0;
+    for (y, &x) in seam.iter().enumerate() {
+        let row = y * width * channels;
+        for range in [
+            row..row + x * channels,
+            row + (x + 1) * channels..row + width * channels,
+        ] {
+            let len = range.len();
+            data.copy_within(range, write);
+            write += len;
+        }
+    }
+    data.truncate(write);
+}
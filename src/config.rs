@@ -1,63 +1,672 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use anyhow::{Context, Ok, Result};
-use clap::Parser;
+use anyhow::{bail, Context, Error, Ok, Result};
+use clap::{Args, Parser, Subcommand};
+use image::codecs::png::CompressionType;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, Rgb};
 
+use crate::carve::{Carver, Direction, Hybrid, SearchMode, Stiffness, Strategy};
 use crate::change::Change;
+use crate::codec::{is_stdio, read_image, EncodeOptions, STDIO};
+use crate::color::ColorSpace;
+use crate::energy::{EnergyFunction, EnergyOptions, MAX_SALIENCY_WEIGHT};
+use crate::layer::{Interpolation, Layer};
+use crate::mask::{Mask, Polygon, Rect, Region, RegionKind, Shape};
+use crate::protect::SkinProtector;
+use crate::seam::SeamFormat;
+use crate::video::{FramePattern, DEFAULT_COHERENCE, DEFAULT_COHERENCE_RADIUS};
 
 #[derive(Debug, Parser)]
 pub struct Config {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Resizes an image to one or more sizes.
+    Resize(ResizeConfig),
+    /// Removes the regions marked with --remove-rect, --remove-poly or
+    /// --regions.
+    Remove(RemoveConfig),
+    /// Writes the energy of each pixel and optionally the seam cost map.
+    Energy(EnergyConfig),
+    /// Prints the seams shrinking would remove without changing the image.
+    Seams(SeamsConfig),
+    /// Prints the dimensions, the cost of carving to a size and how far the
+    /// image can safely shrink.
+    Inspect(InspectConfig),
+    /// Applies seams saved with --seams-out to another image of the same
+    /// size.
+    Replay(ReplayConfig),
+    /// Resizes a numbered frame sequence such as frame_%04d.png, keeping
+    /// each frame's seams close to the previous frame's.
+    Video(VideoConfig),
+}
+
+#[derive(Debug, Args)]
+pub struct ResizeConfig {
+    pub input_path: PathBuf,
+    pub output_path: Option<PathBuf>,
+    #[command(flatten)]
+    pub target: TargetOptions,
+    #[arg(long)]
+    pub debug_path: Option<PathBuf>,
+    /// Writes the carved seams as JSON, or as CSV if the path ends in .csv.
+    #[arg(long)]
+    pub seams_out: Option<PathBuf>,
+    #[arg(long)]
+    pub seams_format: Option<SeamFormat>,
+    #[arg(long, default_value = "carve")]
+    pub strategy: Strategy,
+    #[arg(long)]
+    pub hybrid: bool,
+    #[arg(long)]
+    pub hybrid_max_cost: Option<u32>,
+    #[arg(long, default_value_t = 0.5)]
+    pub hybrid_fraction: f32,
+    #[arg(long, value_parser = parse_filter, default_value = "lanczos3")]
+    pub hybrid_filter: FilterType,
+    /// Switches to a slower carver that needs far less memory when a resize
+    /// would need more than this many bytes. Takes K, M, G and T suffixes.
+    #[arg(long, value_parser = parse_memory)]
+    pub max_memory: Option<usize>,
+    /// Instead of resizing to a target size, removes seams for as long as
+    /// the cheapest costs at most this much energy per pixel.
+    #[arg(long)]
+    pub max_cost: Option<u32>,
+    /// Like --max-cost, using the energy of the median pixel as the cost.
+    #[arg(long)]
+    pub auto: bool,
+    #[command(flatten)]
+    pub carve: CarveOptions,
+    #[command(flatten)]
+    pub output: OutputOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct RemoveConfig {
+    pub input_path: PathBuf,
+    #[command(flatten)]
+    pub decode: DecodeOptions,
+    pub output_path: Option<PathBuf>,
+    /// Grows the image back to its original size afterwards.
+    #[arg(long)]
+    pub restore: bool,
+    #[command(flatten)]
+    pub carve: CarveOptions,
+    #[command(flatten)]
+    pub output: OutputOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct EnergyConfig {
+    pub input_path: PathBuf,
+    #[command(flatten)]
+    pub decode: DecodeOptions,
+    pub output_path: Option<PathBuf>,
+    #[arg(long)]
+    pub cost_path: Option<PathBuf>,
+    #[command(flatten)]
+    pub carve: CarveOptions,
+    #[command(flatten)]
+    pub output: OutputOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct SeamsConfig {
     pub input_path: PathBuf,
+    #[command(flatten)]
+    pub decode: DecodeOptions,
+    /// Where to write the seams, which defaults to stdout.
     pub output_path: Option<PathBuf>,
+    #[arg(long)]
+    pub seams_format: Option<SeamFormat>,
+    #[arg(long, default_value_t = 1)]
+    pub count: usize,
+    #[arg(long, default_value = "vertical")]
+    pub direction: Direction,
+    #[command(flatten)]
+    pub carve: CarveOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectConfig {
+    pub input_path: PathBuf,
+    #[command(flatten)]
+    pub decode: DecodeOptions,
+    #[command(flatten)]
+    pub target: TargetOptions,
+    #[command(flatten)]
+    pub carve: CarveOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplayConfig {
+    pub input_path: PathBuf,
+    #[command(flatten)]
+    pub decode: DecodeOptions,
+    pub output_path: Option<PathBuf>,
+    #[arg(long)]
+    pub seams: PathBuf,
+    #[arg(long)]
+    pub seams_format: Option<SeamFormat>,
+    #[command(flatten)]
+    pub output: OutputOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct VideoConfig {
+    pub input_pattern: FramePattern,
+    pub output_pattern: FramePattern,
+    #[command(flatten)]
+    pub decode: DecodeOptions,
+    #[command(flatten)]
+    pub target: TargetOptions,
+    /// The energy added per pixel a seam moves from where it was in the
+    /// previous frame. Zero carves every frame independently.
+    #[arg(long, default_value_t = DEFAULT_COHERENCE)]
+    pub coherence: i32,
+    /// How far from the previous frame's seams the penalty stops growing.
+    #[arg(long, default_value_t = DEFAULT_COHERENCE_RADIUS)]
+    pub coherence_radius: usize,
+    #[command(flatten)]
+    pub carve: CarveOptions,
+    #[command(flatten)]
+    pub output: OutputOptions,
+}
+
+/// Options for how input images are read.
+#[derive(Debug, Args)]
+pub struct DecodeOptions {
+    /// Lets decoding use up to this many bytes instead of half a gigabyte,
+    /// for very large scans. Takes K, M, G and T suffixes.
+    #[arg(long, value_parser = parse_memory)]
+    pub max_memory: Option<usize>,
+}
+
+/// The sizes to resize to.
+#[derive(Debug, Args)]
+pub struct TargetOptions {
     #[arg(long)]
     pub width: Option<Change>,
     #[arg(long)]
     pub height: Option<Change>,
-    #[arg(long, value_parser = Config::parse_dimensions)]
+    #[arg(long, value_parser = parse_dimensions)]
     pub dimensions: Option<(Change, Change)>,
+}
+
+/// Options for how seams are found, shared by every command.
+#[derive(Debug, Args)]
+pub struct CarveOptions {
+    #[arg(long, default_value_t = 1)]
+    pub max_drift: usize,
+    #[arg(long, default_value_t = 0)]
+    pub diagonal_penalty: u32,
+    #[arg(long, default_value_t = 1)]
+    pub seams_per_pass: usize,
+    /// Searches for each seam at full size, or with `pyramid` on a smaller
+    /// copy of the energy first.
+    #[arg(long, default_value = "full")]
+    pub search: SearchMode,
+    /// How many times the pyramid search halves the energy.
+    #[arg(long, default_value_t = 2)]
+    pub pyramid_levels: usize,
+    /// How many columns either side of a small seam the pyramid search
+    /// refines it within.
+    #[arg(long, default_value_t = 8)]
+    pub search_band: usize,
     #[arg(long)]
-    pub debug_path: Option<PathBuf>,
+    pub alpha_aware: bool,
+    #[arg(long, default_value = "rgb")]
+    pub color_space: ColorSpace,
+    #[arg(long, default_value = "gradient")]
+    pub energy: EnergyFunction,
+    /// How strongly to keep seams out of salient regions, from 0 to 10.
+    #[arg(long, value_parser = parse_saliency, default_value_t = 0.0)]
+    pub saliency: f32,
+    #[arg(long)]
+    pub protect_skin: bool,
+    #[arg(long)]
+    pub protect_rect: Vec<Rect>,
+    #[arg(long)]
+    pub remove_rect: Vec<Rect>,
+    #[arg(long)]
+    pub protect_poly: Vec<Polygon>,
+    #[arg(long)]
+    pub remove_poly: Vec<Polygon>,
+    #[arg(long)]
+    pub regions: Option<PathBuf>,
+    /// Carves an extra layer along with the image, given as
+    /// PATH[:nearest|linear][:ENERGY_WEIGHT].
+    #[arg(long)]
+    pub layer: Vec<LayerArg>,
 }
 
-impl Config {
-    pub fn get_output_path(&mut self, suffix: &str) -> PathBuf {
-        let mut output_path = self
+#[derive(Clone, Debug)]
+pub struct LayerArg {
+    pub path: PathBuf,
+    pub interpolation: Interpolation,
+    pub energy_weight: f32,
+}
+
+impl FromStr for LayerArg {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split(':');
+        let path = parts.next().filter(|path| !path.is_empty());
+        let path = PathBuf::from(path.context("no layer path")?);
+        let interpolation = match parts.next() {
+            None => Interpolation::default(),
+            Some(interpolation) => interpolation.parse()?,
+        };
+        let energy_weight = match parts.next() {
+            None => 0.0,
+            Some(weight) => weight.parse().context("invalid energy weight")?,
+        };
+        if parts.next().is_some() {
+            bail!("expected a layer as PATH[:nearest|linear][:ENERGY_WEIGHT]");
+        }
+        Ok(LayerArg {
+            path,
+            interpolation,
+            energy_weight,
+        })
+    }
+}
+
+impl LayerArg {
+    /// Names the layer after its file.
+    pub fn get_name(&self) -> String {
+        self.path.file_stem().map_or_else(
+            || "layer".into(),
+            |stem| stem.to_string_lossy().into_owned(),
+        )
+    }
+}
+
+/// Options for how output images are written.
+#[derive(Debug, Args)]
+pub struct OutputOptions {
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<ImageFormat>,
+    /// The JPEG quality from 1 to 100. WebP is always written losslessly, so
+    /// only 100 is accepted for it.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub quality: Option<u8>,
+    #[arg(long, value_parser = parse_png_compression, default_value = "fast")]
+    pub png_compression: CompressionType,
+    #[arg(long, value_parser = parse_color)]
+    pub background: Option<Rgb<u8>>,
+}
+
+impl ResizeConfig {
+    pub fn get_output_path(&self, suffix: &str) -> PathBuf {
+        let output_path = self
             .output_path
             .clone()
-            .unwrap_or_else(|| self.get_default_path());
-        if !suffix.is_empty() {
-            let mut file_name = output_path.file_stem().expect("a file name").to_os_string();
-            file_name.push("-");
-            file_name.push(suffix);
-            file_name.push(output_path.extension().expect("a file extension"));
-            output_path.set_file_name(file_name);
+            .unwrap_or_else(|| get_default_path(&self.input_path, "resized"));
+        add_suffix(output_path, suffix)
+    }
+
+    /// Like [`get_output_path`](Self::get_output_path), but a path made up
+    /// from the input's ends in `.gif`, the only format animations can be
+    /// written in.
+    pub fn get_animation_output_path(&self, suffix: &str) -> PathBuf {
+        let output_path = self.output_path.clone().unwrap_or_else(|| {
+            let mut path = get_default_path(&self.input_path, "resized");
+            if !is_stdio(&path) {
+                path.set_extension("gif");
+            }
+            path
+        });
+        add_suffix(output_path, suffix)
+    }
+
+    /// Whether seams are trimmed by cost rather than to a target size.
+    pub fn is_trim(&self) -> bool {
+        self.max_cost.is_some() || self.auto
+    }
+
+    pub fn get_hybrid(&self) -> Option<Hybrid> {
+        if !self.hybrid {
+            return None;
+        }
+        Some(Hybrid {
+            max_seam_cost: self.hybrid_max_cost,
+            max_carve_fraction: self.hybrid_fraction,
+            filter: self.hybrid_filter,
+        })
+    }
+}
+
+impl RemoveConfig {
+    pub fn get_output_path(&self) -> PathBuf {
+        self.output_path
+            .clone()
+            .unwrap_or_else(|| get_default_path(&self.input_path, "removed"))
+    }
+}
+
+impl EnergyConfig {
+    pub fn get_output_path(&self) -> PathBuf {
+        self.output_path
+            .clone()
+            .unwrap_or_else(|| get_default_path(&self.input_path, "energy"))
+    }
+}
+
+impl ReplayConfig {
+    pub fn get_output_path(&self) -> PathBuf {
+        self.output_path
+            .clone()
+            .unwrap_or_else(|| get_default_path(&self.input_path, "replayed"))
+    }
+}
+
+impl TargetOptions {
+    pub fn is_empty(&self) -> bool {
+        self.width.is_none() && self.height.is_none() && self.dimensions.is_none()
+    }
+
+    /// Expands the requested changes into every target size for an image of
+    /// the given size.
+    pub fn get_target_dimensions(
+        &self,
+        dims @ (width, height): (usize, usize),
+    ) -> Result<Vec<(usize, usize)>> {
+        if let Some(dimensions) = &self.dimensions {
+            return collect_both_dimensions(&dimensions.0, &dimensions.1, dims);
+        }
+
+        match (&self.width, &self.height) {
+            (None, None) => bail!("no resize dimensions specified"),
+            (None, Some(height_change)) => Ok(height_change
+                .to_absolutes(height)?
+                .into_iter()
+                .map(|h| (width, h))
+                .collect()),
+            (Some(width_change), None) => Ok(width_change
+                .to_absolutes(width)?
+                .into_iter()
+                .map(|w| (w, height))
+                .collect()),
+            (Some(width_change), Some(height_change)) => {
+                collect_both_dimensions(width_change, height_change, dims)
+            }
+        }
+    }
+}
+
+impl CarveOptions {
+    /// Creates a carver for the image with every option applied.
+    pub fn build_carver(&self, image: &DynamicImage) -> Result<Carver> {
+        let mut carver = Carver::new(image)
+            .with_stiffness(self.get_stiffness())
+            .with_seams_per_pass(self.seams_per_pass)
+            .with_search_mode(self.get_search_mode())
+            .with_energy_options(self.get_energy_options());
+        let mask = self.get_mask()?;
+        if !mask.is_empty() {
+            carver = carver.with_energy_modifier(&mask);
+        }
+        if self.protect_skin {
+            carver = carver.with_energy_modifier(&SkinProtector::default());
+        }
+        for layer in &self.layer {
+            let (image, _) = read_image(&layer.path, None)?;
+            let layer = Layer::from_image(&layer.get_name(), &image)
+                .with_interpolation(layer.interpolation)
+                .with_energy_weight(layer.energy_weight);
+            carver = carver.with_layer(layer)?;
+        }
+        Ok(carver)
+    }
+
+    pub fn get_stiffness(&self) -> Stiffness {
+        Stiffness {
+            max_drift: self.max_drift,
+            diagonal_penalty: self.diagonal_penalty,
         }
-        output_path
     }
 
-    fn get_default_path(&self) -> PathBuf {
-        let mut output_path = self.input_path.clone();
+    pub fn get_search_mode(&self) -> SearchMode {
+        match self.search {
+            SearchMode::Full => SearchMode::Full,
+            SearchMode::Pyramid { .. } => SearchMode::Pyramid {
+                levels: self.pyramid_levels,
+                band: self.search_band,
+            },
+        }
+    }
+
+    pub fn get_energy_options(&self) -> EnergyOptions {
+        EnergyOptions {
+            alpha_aware: self.alpha_aware,
+            color_space: self.color_space,
+            function: self.energy,
+            saliency_weight: self.saliency,
+        }
+    }
 
-        let mut stem = self.input_path.file_stem().unwrap().to_owned();
-        stem.push("-resized");
-        output_path.set_file_name(stem);
+    /// Collects the regions from the command line and the regions file.
+    pub fn get_mask(&self) -> Result<Mask> {
+        let mut mask = match &self.regions {
+            None => Mask::default(),
+            Some(path) => read_regions(path)?,
+        };
+        let shapes = self
+            .protect_rect
+            .iter()
+            .map(|&rect| (RegionKind::Protect, Shape::Rect(rect)))
+            .chain(
+                self.remove_rect
+                    .iter()
+                    .map(|&rect| (RegionKind::Remove, Shape::Rect(rect))),
+            )
+            .chain(
+                self.protect_poly
+                    .iter()
+                    .map(|poly| (RegionKind::Protect, Shape::Polygon(poly.clone()))),
+            )
+            .chain(
+                self.remove_poly
+                    .iter()
+                    .map(|poly| (RegionKind::Remove, Shape::Polygon(poly.clone()))),
+            );
+        for (kind, shape) in shapes {
+            mask.regions.push(Region { kind, shape });
+        }
+        Ok(mask)
+    }
+}
+
+impl OutputOptions {
+    /// Picks the format to write the given path in. An explicit format wins,
+    /// then the path's extension, then the format the input was read in.
+    pub fn get_output_format(
+        &self,
+        path: &Path,
+        input_format: Option<ImageFormat>,
+    ) -> Result<ImageFormat> {
+        self.format
+            .or_else(|| ImageFormat::from_path(path).ok())
+            .or(input_format)
+            .with_context(|| format!("no output format for {path:?}, use --format"))
+    }
+
+    pub fn get_encode_options(&self) -> EncodeOptions {
+        EncodeOptions {
+            quality: self.quality,
+            png_compression: self.png_compression,
+            background: self.background,
+        }
+    }
+}
 
-        let extension = self.input_path.extension().unwrap();
+/// Names a layer's output after the image's, such as `castle-depth.png` for
+/// `castle.jpg`. Layers are always written as PNG to keep their precision.
+pub fn get_layer_path(output_path: &Path, name: &str) -> PathBuf {
+    let mut file_name = output_path.file_stem().expect("a file name").to_os_string();
+    file_name.push("-");
+    file_name.push(name);
+    file_name.push(".png");
+    output_path.with_file_name(file_name)
+}
+
+/// Names the output after the input with the given suffix, such as
+/// `castle-resized.jpg` for `castle.jpg`.
+/// Adds a suffix to the file name of an output path before its extension,
+/// such as `castle-(300, 200).jpg` for `castle.jpg`.
+fn add_suffix(mut output_path: PathBuf, suffix: &str) -> PathBuf {
+    if !suffix.is_empty() && !is_stdio(&output_path) {
+        let mut file_name = output_path.file_stem().expect("a file name").to_os_string();
+        file_name.push("-");
+        file_name.push(suffix);
+        if let Some(extension) = output_path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        output_path.set_file_name(file_name);
+    }
+    output_path
+}
+
+fn get_default_path(input_path: &Path, suffix: &str) -> PathBuf {
+    if is_stdio(input_path) {
+        return PathBuf::from(STDIO);
+    }
+
+    let mut output_path = input_path.to_path_buf();
+
+    let mut stem = input_path.file_stem().expect("a file name").to_owned();
+    stem.push("-");
+    stem.push(suffix);
+    output_path.set_file_name(stem);
+
+    if let Some(extension) = input_path.extension() {
         output_path.set_extension(extension);
+    }
+
+    output_path
+}
 
-        output_path
+fn collect_both_dimensions(
+    width_change: &Change,
+    height_change: &Change,
+    (width, height): (usize, usize),
+) -> Result<Vec<(usize, usize)>> {
+    let mut res = vec![];
+    for w in width_change.to_absolutes(width)? {
+        for h in height_change.to_absolutes(height)? {
+            res.push((w, h));
+        }
     }
+    Ok(res)
+}
+
+fn parse_dimensions(s: &str) -> Result<(Change, Change)> {
+    let mut values = s.split("x");
+    let x = values
+        .next()
+        .and_then(|s| s.parse().ok())
+        .context("no x dimension")?;
+    let y = values
+        .next()
+        .and_then(|s| s.parse().ok())
+        .context("no y dimension")?;
+    Ok((x, y))
+}
+
+#[cfg(feature = "serde")]
+fn read_regions(path: &Path) -> Result<Mask> {
+    let file = std::fs::File::open(path).with_context(|| format!("opening {path:?}"))?;
+    Mask::from_json(std::io::BufReader::new(file))
+}
+
+#[cfg(not(feature = "serde"))]
+fn read_regions(_path: &Path) -> Result<Mask> {
+    bail!("reading regions requires the serde feature")
+}
+
+fn parse_format(s: &str) -> Result<ImageFormat> {
+    match ImageFormat::from_extension(s) {
+        Some(format) if format.writing_enabled() => Ok(format),
+        _ => bail!("unsupported format: {s}"),
+    }
+}
+
+/// Parses a saliency weight, clamping it to the range the energy can hold.
+fn parse_saliency(s: &str) -> Result<f32> {
+    let weight: f32 = s
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid saliency weight: {s}"))?;
+    if weight.is_nan() {
+        bail!("invalid saliency weight: {s}");
+    }
+    Ok(weight.clamp(0.0, MAX_SALIENCY_WEIGHT))
+}
+
+fn parse_png_compression(s: &str) -> Result<CompressionType> {
+    match s {
+        "fast" => Ok(CompressionType::Fast),
+        "default" => Ok(CompressionType::Default),
+        "best" => Ok(CompressionType::Best),
+        _ => bail!("unknown png compression: {s}"),
+    }
+}
+
+/// Parses a number of bytes, optionally followed by a binary K, M, G or T
+/// suffix as in `512M` or `1.5G`.
+fn parse_memory(s: &str) -> Result<usize> {
+    let s = s.trim().trim_end_matches(['B', 'b']).trim_end_matches('i');
+    let (number, shift) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 30),
+        Some((i, 'T' | 't')) => (&s[..i], 40),
+        _ => (s, 0),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid amount of memory: {s}"))?;
+    if number.is_nan() || number < 0.0 {
+        bail!("invalid amount of memory: {s}");
+    }
+    Ok((number * (1u64 << shift) as f64) as usize)
+}
+
+/// Parses a colour given as `#rrggbb` or `r,g,b`.
+fn parse_color(s: &str) -> Result<Rgb<u8>> {
+    if let Some(hex) = s.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6);
+        let value = value.with_context(|| format!("invalid colour: {s}"))?;
+        let [_, r, g, b] = value.to_be_bytes();
+        return Ok(Rgb([r, g, b]));
+    }
+    let channels = s
+        .split(',')
+        .map(|c| c.trim().parse::<u8>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok();
+    match channels.as_deref() {
+        Some(&[r, g, b]) => Ok(Rgb([r, g, b])),
+        _ => bail!("invalid colour: {s}"),
+    }
+}
 
-    fn parse_dimensions(s: &str) -> Result<(Change, Change)> {
-        let mut values = s.split("x");
-        let x = values
-            .next()
-            .and_then(|s| s.parse().ok())
-            .context("no x dimension")?;
-        let y = values
-            .next()
-            .and_then(|s| s.parse().ok())
-            .context("no y dimension")?;
-        Ok((x, y))
+fn parse_filter(s: &str) -> Result<FilterType> {
+    match s {
+        "nearest" => Ok(FilterType::Nearest),
+        "triangle" => Ok(FilterType::Triangle),
+        "catmull-rom" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos3" => Ok(FilterType::Lanczos3),
+        _ => bail!("unknown filter: {s}"),
     }
 }
@@ -1,4 +1,4 @@
-use image::{DynamicImage, GenericImage, Rgba};
+use image::{DynamicImage, GenericImage, GrayImage, Luma, Rgba};
 
 pub fn create_debug_image(image: &mut DynamicImage, points: &[(usize, usize)]) -> DynamicImage {
     let red_pixel = Rgba([255, 0, 0, 255]);
@@ -8,3 +8,16 @@ pub fn create_debug_image(image: &mut DynamicImage, points: &[(usize, usize)]) -
     }
     image
 }
+
+/// Draws a map of values such as pixel energies as a greyscale image, scaled
+/// so that the largest value is white.
+pub fn create_map_image(values: &[Vec<u32>]) -> DynamicImage {
+    let height = values.len();
+    let width = values.first().map_or(0, |row| row.len());
+    let max = values.iter().flatten().copied().max().unwrap_or(0).max(1) as u64;
+    let image = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+        let value = values[y as usize][x as usize] as u64;
+        Luma([(value * 255 / max) as u8])
+    });
+    DynamicImage::ImageLuma8(image)
+}
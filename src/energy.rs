@@ -1,11 +1,57 @@
-use image::{Pixel, Rgba};
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use image::{DynamicImage, Pixel, Rgba};
+
+use crate::color::{delta_e2000, delta_e76, srgb_to_lab, ColorSpace, Lab};
+use crate::grid::Grid;
+
+/// Perceptual differences are small, so their squares are scaled up before
+/// being rounded to whole energies.
+const PERCEPTUAL_SCALE: f32 = 16.0;
+
+/// Entropy is measured in bits, so it is scaled up to be comparable with
+/// squared gradients.
+const ENTROPY_SCALE: f32 = 4096.0;
+
+/// The gradient divided by the histogram of oriented gradients is at most
+/// one, so it is scaled up to keep some precision as a whole energy.
+const HOG_SCALE: u64 = 1 << 16;
+
+/// The energy a fully salient pixel adds at a weight of one, which is about
+/// as much as a strong edge.
+const SALIENCY_SCALE: f32 = 100_000.0;
+
+/// The largest saliency weight the command line accepts. At this weight the
+/// most salient pixels cost about twice the steepest gradient.
+pub const MAX_SALIENCY_WEIGHT: f32 = 10.0;
+
+/// The largest energy the RGB gradient gives a pixel, when all four channels
+/// swing from 0 to 255 along both axes.
+pub const MAX_GRADIENT_ENERGY: u32 = 2 * 4 * 255 * 255;
+
+const LIGHTNESS_BINS: usize = 32;
+const ORIENTATION_BINS: usize = 8;
 
 #[derive(Clone, Debug)]
 pub struct PixelEnergyPoint {
     pub pixel: Rgba<u8>,
+    /// The pixel's colour in CIELAB. This is only filled in when the colour
+    /// space or energy function needs it.
+    pub lab: Lab,
+    /// How much the pixel stands out, from 0 to 1. This is only filled in
+    /// when saliency is part of the energy.
+    pub saliency: f32,
+    /// Added to the energy to protect the pixel from being carved, or taken
+    /// away to prefer carving it.
+    pub bias: i32,
     pub energy: u32,
     pub path_cost: u32,
     pub original_position: (usize, usize),
+    /// Where the carver keeps the values of any auxiliary layers for this
+    /// pixel, so that points stay cheap to copy however many layers there are.
+    pub layer_sample: usize,
 }
 
 impl PixelEnergyPoint {
@@ -50,19 +96,263 @@ impl PixelEnergyPoint {
         sum
     }
 
+    /// Like `square_gradient`, but colours are weighted by their alpha so
+    /// the colour of transparent pixels doesn't count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate image;
+    /// # extern crate rmr;
+    /// # use image::Rgba;
+    /// # use rmr::energy::PixelEnergyPoint;
+    /// # fn main() {
+    /// let a: PixelEnergyPoint = Rgba([255, 0, 0, 0]).into();
+    /// let b: PixelEnergyPoint = Rgba([0, 0, 0, 255]).into();
+    ///
+    /// let result = a.premultiplied_square_gradient(&b);
+    /// assert_eq!(65025, result);
+    /// # }
+    /// ```
+    pub fn premultiplied_square_gradient(&self, other: &PixelEnergyPoint) -> u32 {
+        let a: PixelEnergyPoint = Rgba(premultiply_pixel_data(&self.pixel.0)).into();
+        let b: PixelEnergyPoint = Rgba(premultiply_pixel_data(&other.pixel.0)).into();
+        a.square_gradient(&b)
+    }
+
+    /// Converts the pixel's colour to CIELAB for the energies that use it.
+    pub fn calculate_lab(&mut self) {
+        let [r, g, b, _] = self.pixel.0;
+        self.lab = srgb_to_lab([r, g, b]);
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.pixel.0[3] == 0
+    }
+
+    /// Blends two points into a new one, which is taken to have come from
+    /// the same place as `self`. It shares the layer values of `self` too.
     pub fn average(&self, other: &PixelEnergyPoint) -> PixelEnergyPoint {
         let data = average_pixel_data(&self.pixel.0, &other.pixel.0);
-        Rgba(data).into()
+        let mut pep: PixelEnergyPoint = Rgba(data).into();
+        pep.lab = [0, 1, 2].map(|i| (self.lab[i] + other.lab[i]) / 2.0);
+        pep.saliency = (self.saliency + other.saliency) / 2.0;
+        pep.bias = ((self.bias as i64 + other.bias as i64) / 2) as i32;
+        pep.original_position = self.original_position;
+        pep.layer_sample = self.layer_sample;
+        pep
     }
 }
 
+/// Controls how the energy of a pixel is computed from its neighbours.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EnergyOptions {
+    /// Gives fully transparent pixels no energy so they are removed first.
+    /// Edges between two transparent pixels are ignored as well.
+    pub alpha_aware: bool,
+    pub color_space: ColorSpace,
+    pub function: EnergyFunction,
+    /// How strongly the saliency of a pixel is blended into its energy. Zero
+    /// leaves saliency out entirely.
+    pub saliency_weight: f32,
+}
+
+impl EnergyOptions {
+    /// Calculates the energy of the point at the given coordinates using the
+    /// configured energy function.
+    pub fn grid_energy(&self, grid: &Grid<PixelEnergyPoint>, x: usize, y: usize) -> u32 {
+        let center = grid.get(x, y);
+        let gradient = self.pixel_energy(center, grid.get_adjacent(x, y));
+        let energy = match self.function {
+            EnergyFunction::Gradient => gradient,
+            EnergyFunction::Entropy { radius } => {
+                let entropy = window_entropy(grid.iter_window(x, y, radius));
+                gradient + (entropy * ENTROPY_SCALE).round() as u32
+            }
+            EnergyFunction::Hog { radius } => {
+                let mut histogram = [0u64; ORIENTATION_BINS];
+                for ((x, y), pep) in grid.iter_window_with_coords(x, y, radius) {
+                    let (left, right, up, down) = grid.get_adjacent(x, y);
+                    let dx = right.lab[0] - left.lab[0];
+                    let dy = down.lab[0] - up.lab[0];
+                    // Orientations are unsigned, so opposite directions share a bin.
+                    let angle = dy.atan2(dx).rem_euclid(PI);
+                    let bin = (angle / PI * ORIENTATION_BINS as f32) as usize % ORIENTATION_BINS;
+                    histogram[bin] += self.pixel_energy(pep, (left, right, up, down)) as u64;
+                }
+                let max_bin = histogram.into_iter().max().unwrap_or(0).max(1);
+                (gradient as u64 * HOG_SCALE / max_bin).min(u32::MAX as u64) as u32
+            }
+        };
+        let saliency = (center.saliency * self.saliency_weight * SALIENCY_SCALE).round() as u32;
+        energy
+            .saturating_add(saliency)
+            .saturating_add_signed(center.bias)
+    }
+
+    pub fn uses_saliency(&self) -> bool {
+        self.saliency_weight > 0.0
+    }
+
+    /// Whether the energy needs the colour of each pixel in CIELAB.
+    pub fn uses_lab(&self) -> bool {
+        self.color_space != ColorSpace::Rgb || self.function != EnergyFunction::Gradient
+    }
+
+    /// Calculates the energy of a pixel from its adjacent pixels.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate image;
+    /// # extern crate rmr;
+    /// # use image::Rgba;
+    /// # use rmr::color::ColorSpace;
+    /// # use rmr::energy::{EnergyOptions, PixelEnergyPoint};
+    /// # fn main() {
+    /// let clear: PixelEnergyPoint = Rgba([9, 200, 13, 0]).into();
+    /// let noise: PixelEnergyPoint = Rgba([250, 7, 91, 0]).into();
+    /// let solid: PixelEnergyPoint = Rgba([0, 0, 0, 255]).into();
+    ///
+    /// let options = EnergyOptions {
+    ///     alpha_aware: true,
+    ///     ..EnergyOptions::default()
+    /// };
+    /// let adjacent = (&noise, &clear, &solid, &solid);
+    /// assert_eq!(0, options.pixel_energy(&solid, adjacent));
+    /// assert_eq!(0, options.pixel_energy(&clear, adjacent));
+    ///
+    /// let options = EnergyOptions::default();
+    /// assert_eq!(101414, options.pixel_energy(&solid, adjacent));
+    ///
+    /// let mut blue: PixelEnergyPoint = Rgba([0, 0, 255, 255]).into();
+    /// let mut green: PixelEnergyPoint = Rgba([0, 255, 0, 255]).into();
+    /// let mut solid = solid.clone();
+    /// for pep in [&mut blue, &mut green, &mut solid] {
+    ///     pep.calculate_lab();
+    /// }
+    /// let options = EnergyOptions {
+    ///     color_space: ColorSpace::Luminance,
+    ///     ..EnergyOptions::default()
+    /// };
+    /// let to_blue = options.pixel_energy(&solid, (&solid, &blue, &solid, &solid));
+    /// let to_green = options.pixel_energy(&solid, (&solid, &green, &solid, &solid));
+    /// assert!(to_blue < to_green);
+    ///
+    /// // Only the transparency of a fully transparent pixel counts.
+    /// let (mut clear, mut noise) = (clear, noise);
+    /// clear.calculate_lab();
+    /// noise.calculate_lab();
+    /// let options = EnergyOptions {
+    ///     alpha_aware: true,
+    ///     color_space: ColorSpace::Lab76,
+    ///     ..EnergyOptions::default()
+    /// };
+    /// let to_clear = options.pixel_energy(&solid, (&clear, &solid, &solid, &solid));
+    /// let to_noise = options.pixel_energy(&solid, (&noise, &solid, &solid, &solid));
+    /// assert_eq!(65025, to_clear);
+    /// assert_eq!(to_clear, to_noise);
+    /// # }
+    /// ```
+    pub fn pixel_energy(
+        &self,
+        center: &PixelEnergyPoint,
+        (left, right, up, down): Adjacent,
+    ) -> u32 {
+        if self.alpha_aware && center.is_transparent() {
+            return 0;
+        }
+        self.gradient(left, right) + self.gradient(up, down)
+    }
+
+    fn gradient(&self, a: &PixelEnergyPoint, b: &PixelEnergyPoint) -> u32 {
+        if self.alpha_aware && a.is_transparent() && b.is_transparent() {
+            return 0;
+        }
+        let difference = match self.color_space {
+            ColorSpace::Rgb if self.alpha_aware => return a.premultiplied_square_gradient(b),
+            ColorSpace::Rgb => return a.square_gradient(b),
+            // The colour of a fully transparent pixel means nothing.
+            _ if self.alpha_aware && (a.is_transparent() || b.is_transparent()) => 0.0,
+            ColorSpace::Lab76 => delta_e76(&a.lab, &b.lab),
+            ColorSpace::Lab2000 => delta_e2000(&a.lab, &b.lab),
+            ColorSpace::Luminance => (a.lab[0] - b.lab[0]).abs(),
+        };
+        // Alpha isn't part of the colour, but transparency edges still count.
+        let alpha_difference = (a.pixel.0[3] as i32 - b.pixel.0[3] as i32).pow(2) as u32;
+        (difference * difference * PERCEPTUAL_SCALE).round() as u32 + alpha_difference
+    }
+}
+
+/// Adjusts the energy of pixels based on the whole image, for example to
+/// protect regions that shouldn't be carved. The biases of several modifiers
+/// add up.
+pub trait EnergyModifier {
+    /// Returns rows of biases that are the same size as the image. Energy
+    /// never drops below zero, however negative the bias.
+    fn bias(&self, image: &DynamicImage) -> Vec<Vec<i32>>;
+}
+
+/// The function used to turn a pixel's neighbourhood into energy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EnergyFunction {
+    /// The squared gradient to the adjacent pixels.
+    #[default]
+    Gradient,
+    /// The gradient plus the entropy of the lightness in a window of the
+    /// given radius around the pixel.
+    Entropy { radius: usize },
+    /// The gradient divided by the largest bin of a histogram of oriented
+    /// gradients in a window of the given radius around the pixel.
+    Hog { radius: usize },
+}
+
+impl EnergyFunction {
+    /// How far away a change to the image can affect a pixel's energy.
+    pub fn reach(&self) -> usize {
+        match *self {
+            EnergyFunction::Gradient => 1,
+            // Window pixels use their own neighbours, and seams shift rows
+            // that were already handled, so allow one extra pixel.
+            EnergyFunction::Entropy { radius } | EnergyFunction::Hog { radius } => radius + 1,
+        }
+    }
+}
+
+impl FromStr for EnergyFunction {
+    type Err = Error;
+
+    /// Parses the name of an energy function using the window sizes from
+    /// the seam carving paper, 9x9 for entropy and 11x11 for HoG.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gradient" => Ok(EnergyFunction::Gradient),
+            "entropy" => Ok(EnergyFunction::Entropy { radius: 4 }),
+            "hog" => Ok(EnergyFunction::Hog { radius: 5 }),
+            _ => bail!("unknown energy function: {s}"),
+        }
+    }
+}
+
+/// The left, right, up and down neighbours of a pixel.
+pub type Adjacent<'a> = (
+    &'a PixelEnergyPoint,
+    &'a PixelEnergyPoint,
+    &'a PixelEnergyPoint,
+    &'a PixelEnergyPoint,
+);
+
 impl From<Rgba<u8>> for PixelEnergyPoint {
     fn from(pixel: Rgba<u8>) -> Self {
         PixelEnergyPoint {
             pixel,
+            lab: [0.0; 3],
+            saliency: 0.0,
+            bias: 0,
             energy: 0,
             path_cost: 0,
             original_position: (0, 0),
+            layer_sample: 0,
         }
     }
 }
@@ -75,3 +365,32 @@ fn average_pixel_data(pixel1: &[u8; 4], pixel2: &[u8; 4]) -> [u8; 4] {
         ((pixel1[3] as u16 + pixel2[3] as u16) / 2) as u8,
     ]
 }
+
+/// The entropy in bits of the lightness of the given pixels.
+fn window_entropy<'a>(window: impl Iterator<Item = &'a PixelEnergyPoint>) -> f32 {
+    let mut histogram = [0u32; LIGHTNESS_BINS];
+    let mut count = 0;
+    for pep in window {
+        let bin = (pep.lab[0] / 100.0 * (LIGHTNESS_BINS - 1) as f32).round() as usize;
+        histogram[bin.min(LIGHTNESS_BINS - 1)] += 1;
+        count += 1;
+    }
+    histogram
+        .into_iter()
+        .filter(|&n| n > 0)
+        .map(|n| {
+            let p = n as f32 / count as f32;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn premultiply_pixel_data(pixel: &[u8; 4]) -> [u8; 4] {
+    let alpha = pixel[3] as u16;
+    [
+        (pixel[0] as u16 * alpha / 255) as u8,
+        (pixel[1] as u16 * alpha / 255) as u8,
+        (pixel[2] as u16 * alpha / 255) as u8,
+        pixel[3],
+    ]
+}
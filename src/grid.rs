@@ -1,4 +1,6 @@
 use std::cell::Cell;
+use std::mem;
+use std::ops::Range;
 use std::rc::{Rc, Weak};
 
 use image::{DynamicImage, GenericImageView};
@@ -62,6 +64,12 @@ impl<T> Grid<T> {
         self.points[0].len()
     }
 
+    /// The bytes each point takes up in the grid, not counting anything it
+    /// allocates or the position shared with its tokens.
+    pub fn point_size() -> usize {
+        mem::size_of::<Item<T>>()
+    }
+
     pub fn get(&self, x: usize, y: usize) -> &T {
         &self.points[y][x].val
     }
@@ -122,8 +130,33 @@ impl<T> Grid<T> {
         self.iter_parents_with_coords(x, y).collect()
     }
 
-    pub fn iter_parents_with_coords(&self, x: usize, y: usize) -> ParentIter<T> {
-        ParentIter::new(self, x, y)
+    pub fn iter_parents_with_coords(&self, x: usize, y: usize) -> ParentIter<'_, T> {
+        self.iter_parents_within(x, y, 1)
+    }
+
+    /// Iterates over the parents of a point that are at most `max_drift`
+    /// columns to either side of it.
+    pub fn iter_parents_within(&self, x: usize, y: usize, max_drift: usize) -> ParentIter<'_, T> {
+        ParentIter::new(self, x, y, max_drift)
+    }
+
+    /// Iterates over the points at most `radius` rows and columns away from
+    /// the given point. Unlike `get_adjacent` the window doesn't wrap around,
+    /// so it is clamped to the grid instead.
+    pub fn iter_window(&self, x: usize, y: usize, radius: usize) -> impl Iterator<Item = &T> {
+        self.iter_window_with_coords(x, y, radius)
+            .map(|(_, item)| item)
+    }
+
+    pub fn iter_window_with_coords(
+        &self,
+        x: usize,
+        y: usize,
+        radius: usize,
+    ) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let columns = x.saturating_sub(radius)..(x + radius + 1).min(self.width());
+        let rows = y.saturating_sub(radius)..(y + radius + 1).min(self.height());
+        rows.flat_map(move |y| columns.clone().map(move |x| ((x, y), self.get(x, y))))
     }
 
     pub fn get_row(&self, y: usize) -> Vec<&T> {
@@ -227,6 +260,22 @@ impl<T> Grid<T> {
         }
     }
 
+    /// Inserts a point into a row, shifting the rest of the row right. The
+    /// row is left one point longer than the others, so every row needs a
+    /// point inserted before the grid is used again.
+    pub fn insert_point(&mut self, x: usize, y: usize, val: T) {
+        let rotated = self.is_rotated();
+        let row = &mut self.points[y];
+        row.insert(x, Item { val, pos: None });
+        for (x, item) in row.iter_mut().enumerate().skip(x + 1) {
+            if !rotated {
+                item.update_pos(x, y);
+            } else {
+                item.update_pos(y, x);
+            }
+        }
+    }
+
     pub fn make_token(&mut self, x: usize, y: usize) -> Token {
         let master = Rc::new(Cell::new(self.rotate_point((x, y))));
         let position = Rc::downgrade(&master);
@@ -250,6 +299,17 @@ impl<T> Grid<T> {
         [left, right, up, down]
     }
 
+    pub fn make_window_tokens(&mut self, x: usize, y: usize, radius: usize) -> Vec<Token> {
+        let coords: Vec<_> = self
+            .iter_window_with_coords(x, y, radius)
+            .map(|(coord, _)| coord)
+            .collect();
+        coords
+            .into_iter()
+            .map(|(x, y)| self.make_token(x, y))
+            .collect()
+    }
+
     pub fn trade(&self, token: Token) -> Option<&T> {
         token.try_get().map(|(x, y)| &self.get_internal(x, y).val)
     }
@@ -260,6 +320,11 @@ impl<T> Grid<T> {
             .map(move |(x, y)| &mut self.get_mut_internal(x, y).val)
     }
 
+    /// Looks up where the token's point currently is in the grid.
+    pub fn get_token_position(&self, token: &Token) -> Option<(usize, usize)> {
+        token.try_get().map(|point| self.rotate_point(point))
+    }
+
     pub fn get_token_adjacent(&self, token: &Token) -> Option<(&T, &T, &T, &T)> {
         token
             .try_get()
@@ -310,6 +375,11 @@ impl<T: Clone> Grid<T> {
             }
             self.points[y][x] = clone;
         }
+        // The last point is now a stale duplicate. Dropping its position
+        // stops later shifts of the same row from moving live tokens.
+        if let Some(last) = self.points[y].last_mut() {
+            last.pos = None;
+        }
     }
 
     pub fn shift_row_right_from_point(&mut self, x: usize, y: usize) {
@@ -375,40 +445,30 @@ impl<'a> From<&'a DynamicImage> for Grid<PixelEnergyPoint> {
     }
 }
 
-pub enum ParentIter<'grid, T: 'grid> {
-    Done,
-    Right {
-        x: usize,
-        y: usize,
-        grid: &'grid Grid<T>,
-    },
-    Middle {
-        x: usize,
-        y: usize,
-        grid: &'grid Grid<T>,
-    },
-    Left {
-        x: usize,
-        y: usize,
-        grid: &'grid Grid<T>,
-    },
+pub struct ParentIter<'grid, T: 'grid> {
+    grid: &'grid Grid<T>,
+    y: usize,
+    columns: Range<usize>,
 }
 
 impl<'grid, T> ParentIter<'grid, T> {
-    fn new(grid: &'grid Grid<T>, mut x: usize, mut y: usize) -> Self {
+    fn new(grid: &'grid Grid<T>, x: usize, y: usize, max_drift: usize) -> Self {
         if y == 0 {
             // We can't move up. We're already done.
-            return ParentIter::Done;
+            return ParentIter {
+                grid,
+                y,
+                columns: 0..0,
+            };
         }
-        // Move up.
-        y -= 1;
-        if x == 0 {
-            // We can't move left. So this cell only has two parents.
-            return ParentIter::Middle { x, y, grid };
+        // Move up and clamp the reachable columns to the grid.
+        let lo = x.saturating_sub(max_drift);
+        let hi = (x + max_drift + 1).min(grid.width());
+        ParentIter {
+            grid,
+            y: y - 1,
+            columns: lo..hi,
         }
-        // Move left.
-        x -= 1;
-        ParentIter::Left { x, y, grid }
     }
 }
 
@@ -416,36 +476,7 @@ impl<'grid, T> Iterator for ParentIter<'grid, T> {
     type Item = ((usize, usize), &'grid T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let res;
-        match self {
-            ParentIter::Done => return None,
-            ParentIter::Right { x, y, grid } => {
-                let (x, y) = (*x, *y);
-                res = Some(((x, y), grid.get(x, y)));
-                *self = ParentIter::Done;
-            }
-            ParentIter::Middle { x, y, grid } => {
-                let (x, y) = (*x, *y);
-                res = Some(((x, y), grid.get(x, y)));
-                if x == grid.width() - 1 {
-                    // The iterator can't move right without falling off the grid.
-                    *self = ParentIter::Done;
-                } else {
-                    *self = ParentIter::Right { x: x + 1, y, grid };
-                }
-            }
-            ParentIter::Left { x, y, grid } => {
-                let (x, y) = (*x, *y);
-                res = Some(((x, y), grid.get(x, y)));
-                if x == grid.width() - 1 {
-                    // The iterator can't move right without falling off the grid.
-                    // This shouldn't happen unless the grid somehow changed.
-                    *self = ParentIter::Done;
-                } else {
-                    *self = ParentIter::Middle { x: x + 1, y, grid };
-                }
-            }
-        }
-        res
+        let (grid, y) = (self.grid, self.y);
+        self.columns.next().map(|x| ((x, y), grid.get(x, y)))
     }
 }
@@ -0,0 +1,300 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageBuffer, Luma};
+
+use crate::energy::{Adjacent, PixelEnergyPoint};
+
+/// The energy a step from 0 to 1 between neighbours adds at a weight of one,
+/// which is about as much as a strong edge.
+const LAYER_SCALE: f32 = 100_000.0;
+
+/// How a layer is filled in where seams are inserted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Copies the neighbour, which keeps labels and ids intact.
+    #[default]
+    Nearest,
+    /// Averages the neighbours, for continuous values such as depth.
+    Linear,
+}
+
+impl FromStr for Interpolation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "nearest" => Ok(Interpolation::Nearest),
+            "linear" => Ok(Interpolation::Linear),
+            _ => bail!("unknown interpolation: {s}"),
+        }
+    }
+}
+
+/// Extra per-pixel values carried through a carve alongside the colour, such
+/// as a depth map, a label map or a normal map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    /// The values row by row, with the channels of each pixel together.
+    pub data: Vec<f32>,
+    pub interpolation: Interpolation,
+    /// How strongly differences between neighbouring values are added to the
+    /// energy. Zero leaves the layer out of the energy.
+    pub energy_weight: f32,
+}
+
+impl Layer {
+    pub fn new(
+        name: &str,
+        (width, height): (usize, usize),
+        channels: usize,
+        data: Vec<f32>,
+    ) -> Result<Self> {
+        if channels == 0 || data.len() != width * height * channels {
+            bail!(
+                "layer {name} has {} values, not {width}x{height}x{channels}",
+                data.len()
+            );
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            width,
+            height,
+            channels,
+            data,
+            interpolation: Interpolation::default(),
+            energy_weight: 0.0,
+        })
+    }
+
+    /// Reads a layer from an image, keeping its channels and scaling its
+    /// values to between 0 and 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate image;
+    /// # extern crate rmr;
+    /// # use image::{DynamicImage, GrayImage, Luma};
+    /// # use rmr::layer::Layer;
+    /// # fn main() {
+    /// let image = DynamicImage::ImageLuma8(GrayImage::from_pixel(4, 2, Luma([255])));
+    /// let layer = Layer::from_image("depth", &image);
+    /// assert_eq!((4, 2, 1), (layer.width, layer.height, layer.channels));
+    /// assert_eq!(1.0, layer.get(3, 1)[0]);
+    /// # }
+    /// ```
+    pub fn from_image(name: &str, image: &DynamicImage) -> Self {
+        let channels = image.color().channel_count() as usize;
+        let data = match channels {
+            1 => image.to_luma32f().into_raw(),
+            2 => image.to_luma_alpha32f().into_raw(),
+            3 => image.to_rgb32f().into_raw(),
+            _ => image.to_rgba32f().into_raw(),
+        };
+        let size = (image.width() as usize, image.height() as usize);
+        Self::new(name, size, channels, data).expect("the image's own size")
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn with_energy_weight(mut self, energy_weight: f32) -> Self {
+        self.energy_weight = energy_weight;
+        self
+    }
+
+    /// The channels of the pixel at the given coordinates.
+    pub fn get(&self, x: usize, y: usize) -> &[f32] {
+        let start = (y * self.width + x) * self.channels;
+        &self.data[start..start + self.channels]
+    }
+
+    /// Converts the layer to a 16 bit image, clamping values to 0 to 1.
+    pub fn to_image(&self) -> DynamicImage {
+        let data = self
+            .data
+            .iter()
+            .map(|value| (value.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16)
+            .collect();
+        let (width, height) = (self.width as u32, self.height as u32);
+        let image = match self.channels {
+            1 => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16),
+            2 => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA16),
+            3 => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16),
+            _ => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16),
+        };
+        image.expect("the layer's own size")
+    }
+
+    /// Scales the layer to a new size, using a filter that suits its
+    /// interpolation.
+    pub(crate) fn resize(&self, width: usize, height: usize) -> Layer {
+        let filter = match self.interpolation {
+            Interpolation::Nearest => FilterType::Nearest,
+            Interpolation::Linear => FilterType::Triangle,
+        };
+        let mut data = vec![0.0; width * height * self.channels];
+        for channel in 0..self.channels {
+            let plane: ImageBuffer<Luma<f32>, Vec<f32>> =
+                ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+                    Luma([self.get(x as usize, y as usize)[channel]])
+                });
+            let plane = imageops::resize(&plane, width as u32, height as u32, filter);
+            for (i, pixel) in plane.pixels().enumerate() {
+                data[i * self.channels + channel] = pixel.0[0];
+            }
+        }
+        Layer {
+            width,
+            height,
+            data,
+            ..self.clone()
+        }
+    }
+}
+
+/// Where a layer's values are kept in each sample of [`LayerSamples`].
+#[derive(Clone, Debug)]
+pub(crate) struct LayerSlot {
+    pub name: String,
+    pub offset: usize,
+    pub channels: usize,
+    pub interpolation: Interpolation,
+    pub energy_weight: f32,
+}
+
+impl LayerSlot {
+    fn values<'a>(&self, samples: &'a LayerSamples, pep: &PixelEnergyPoint) -> &'a [f32] {
+        &samples.get(pep.layer_sample)[self.offset..self.offset + self.channels]
+    }
+}
+
+/// The values of every attached layer, kept apart from the points so that
+/// carving copies an index instead of a list. Each sample holds one value
+/// per channel of every layer, and samples are only ever added, so points
+/// removed and restored later still find theirs.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LayerSamples {
+    len: usize,
+    stride: usize,
+    values: Vec<f32>,
+}
+
+impl LayerSamples {
+    /// Creates `len` samples without any values yet.
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            ..Self::default()
+        }
+    }
+
+    /// The number of values in each sample.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn get(&self, sample: usize) -> &[f32] {
+        &self.values[sample * self.stride..(sample + 1) * self.stride]
+    }
+
+    pub fn get_mut(&mut self, sample: usize) -> &mut [f32] {
+        &mut self.values[sample * self.stride..(sample + 1) * self.stride]
+    }
+
+    /// Adds room for another layer with the given number of channels to
+    /// every sample, filled with zeros.
+    pub fn widen(&mut self, channels: usize) {
+        let stride = self.stride + channels;
+        let mut values = Vec::with_capacity(self.len * stride);
+        for sample in 0..self.len {
+            values.extend_from_slice(self.get(sample));
+            values.resize(values.len() + channels, 0.0);
+        }
+        self.stride = stride;
+        self.values = values;
+    }
+
+    /// Adds a copy of a sample and returns the new sample.
+    fn push_copy(&mut self, sample: usize) -> usize {
+        self.values
+            .extend_from_within(sample * self.stride..(sample + 1) * self.stride);
+        self.len += 1;
+        self.len - 1
+    }
+}
+
+/// Adds up the weighted squared differences of each layer between a pixel's
+/// horizontal and vertical neighbours.
+pub(crate) fn layer_energy(
+    slots: &[LayerSlot],
+    samples: &LayerSamples,
+    (left, right, up, down): Adjacent,
+) -> u32 {
+    let mut energy = 0.0;
+    for slot in slots.iter().filter(|slot| slot.energy_weight > 0.0) {
+        let square_difference = |a: &PixelEnergyPoint, b: &PixelEnergyPoint| -> f32 {
+            slot.values(samples, a)
+                .iter()
+                .zip(slot.values(samples, b))
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum()
+        };
+        let difference = square_difference(left, right) + square_difference(up, down);
+        energy += difference * slot.energy_weight * LAYER_SCALE;
+    }
+    energy.round().min(u32::MAX as f32) as u32
+}
+
+/// Adds the layer values of a point inserted between two others, returning
+/// its sample.
+pub(crate) fn interpolate_layers(
+    slots: &[LayerSlot],
+    samples: &mut LayerSamples,
+    left: &PixelEnergyPoint,
+    right: &PixelEnergyPoint,
+) -> usize {
+    let sample = samples.push_copy(left.layer_sample);
+    for slot in slots {
+        if slot.interpolation == Interpolation::Linear {
+            for i in slot.offset..slot.offset + slot.channels {
+                let other = samples.get(right.layer_sample)[i];
+                let value = &mut samples.get_mut(sample)[i];
+                *value = (*value + other) / 2.0;
+            }
+        }
+    }
+    sample
+}
+
+/// Rebuilds a layer from the values of rows of points.
+pub(crate) fn collect_layer(
+    slot: &LayerSlot,
+    samples: &LayerSamples,
+    rows: &[Vec<&PixelEnergyPoint>],
+) -> Layer {
+    let height = rows.len();
+    let width = rows.first().map_or(0, |row| row.len());
+    let data = rows
+        .iter()
+        .flatten()
+        .flat_map(|pep| slot.values(samples, pep).iter().copied())
+        .collect();
+    Layer {
+        name: slot.name.clone(),
+        width,
+        height,
+        channels: slot.channels,
+        data,
+        interpolation: slot.interpolation,
+        energy_weight: slot.energy_weight,
+    }
+}
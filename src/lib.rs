@@ -1,6 +1,16 @@
 pub mod carve;
 pub mod change;
+pub mod codec;
+pub mod color;
+pub mod compact;
 pub mod config;
 pub mod debug;
 pub mod energy;
 pub mod grid;
+pub mod layer;
+pub mod mask;
+pub mod protect;
+pub mod remap;
+pub mod saliency;
+pub mod seam;
+pub mod video;
@@ -0,0 +1,239 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+use image::DynamicImage;
+
+use crate::energy::EnergyModifier;
+
+/// A rectangle in pixels from its top left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x..self.x.saturating_add(self.width)).contains(&x)
+            && (self.y..self.y.saturating_add(self.height)).contains(&y)
+    }
+}
+
+impl FromStr for Rect {
+    type Err = Error;
+
+    /// Parses a rectangle of the form `x,y,w,h`.
+    fn from_str(s: &str) -> Result<Self> {
+        let values = parse_list::<u32>(s)?;
+        match *values.as_slice() {
+            [x, y, width, height] => Ok(Rect {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => bail!("expected a rectangle as x,y,w,h"),
+        }
+    }
+}
+
+/// A closed polygon through the given points.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(transparent)
+)]
+pub struct Polygon {
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Polygon {
+    /// Checks whether a point is inside using the even-odd rule.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rmr;
+    /// # use rmr::mask::Polygon;
+    /// # fn main() {
+    /// let triangle: Polygon = "0,0,10,0,0,10".parse().unwrap();
+    /// assert!(triangle.contains(2.0, 2.0));
+    /// assert!(!triangle.contains(8.0, 8.0));
+    /// # }
+    /// ```
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let mut inside = false;
+        let mut previous = match self.points.last() {
+            None => return false,
+            Some(&point) => point,
+        };
+        for &(x1, y1) in &self.points {
+            let (x0, y0) = previous;
+            if (y1 > y) != (y0 > y) && x < (x0 - x1) * (y - y1) / (y0 - y1) + x1 {
+                inside = !inside;
+            }
+            previous = (x1, y1);
+        }
+        inside
+    }
+}
+
+impl FromStr for Polygon {
+    type Err = Error;
+
+    /// Parses a polygon of the form `x1,y1,x2,y2,x3,y3,...`.
+    fn from_str(s: &str) -> Result<Self> {
+        let values = parse_list::<f32>(s)?;
+        if values.len() < 6 || values.len() % 2 != 0 {
+            bail!("expected at least three points as x1,y1,x2,y2,x3,y3");
+        }
+        let points = values.chunks(2).map(|p| (p[0], p[1])).collect();
+        Ok(Polygon { points })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Shape {
+    Rect(Rect),
+    Polygon(Polygon),
+}
+
+impl Shape {
+    fn contains(&self, x: u32, y: u32) -> bool {
+        match self {
+            Shape::Rect(rect) => rect.contains(x, y),
+            // Pixels are sampled at their centres.
+            Shape::Polygon(polygon) => polygon.contains(x as f32 + 0.5, y as f32 + 0.5),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum RegionKind {
+    /// Keeps seams out of the region.
+    Protect,
+    /// Draws seams into the region.
+    Remove,
+}
+
+/// A region of the image that is protected from or marked for carving.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Region {
+    pub kind: RegionKind,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub shape: Shape,
+}
+
+/// A set of regions rasterised into energy biases.
+#[derive(Clone, Debug)]
+pub struct Mask {
+    pub regions: Vec<Region>,
+    /// The energy added to protected pixels.
+    pub protect_bias: i32,
+    /// The energy added to pixels marked for removal. Energy never drops
+    /// below zero, so this makes those pixels free to carve.
+    pub remove_bias: i32,
+}
+
+impl Default for Mask {
+    fn default() -> Self {
+        Self {
+            regions: vec![],
+            protect_bias: 200_000,
+            remove_bias: -1_000_000,
+        }
+    }
+}
+
+impl Mask {
+    pub fn new(regions: Vec<Region>) -> Self {
+        Self {
+            regions,
+            ..Self::default()
+        }
+    }
+
+    /// Reads regions from JSON, as a list of objects such as
+    /// `{"kind": "protect", "rect": {"x": 1, "y": 2, "width": 3, "height": 4}}`
+    /// or `{"kind": "remove", "polygon": [[0, 0], [8, 0], [4, 6]]}`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rmr;
+    /// # use rmr::mask::{Mask, Rect, RegionKind, Shape};
+    /// # fn main() {
+    /// let json = r#"[{"kind": "remove", "rect": {"x": 1, "y": 2, "width": 3, "height": 4}}]"#;
+    /// let mask = Mask::from_json(json.as_bytes()).unwrap();
+    /// assert_eq!(RegionKind::Remove, mask.regions[0].kind);
+    /// assert_eq!(
+    ///     Shape::Rect(Rect { x: 1, y: 2, width: 3, height: 4 }),
+    ///     mask.regions[0].shape
+    /// );
+    /// # }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_json<R: std::io::Read>(reader: R) -> Result<Self> {
+        let regions = serde_json::from_reader(reader).context("invalid regions")?;
+        Ok(Self::new(regions))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn has_removals(&self) -> bool {
+        self.regions
+            .iter()
+            .any(|region| region.kind == RegionKind::Remove)
+    }
+}
+
+impl EnergyModifier for Mask {
+    /// Later regions take precedence where regions overlap.
+    fn bias(&self, image: &DynamicImage) -> Vec<Vec<i32>> {
+        let (width, height) = (image.width(), image.height());
+        let mut bias = vec![vec![0; width as usize]; height as usize];
+        for region in &self.regions {
+            let value = match region.kind {
+                RegionKind::Protect => self.protect_bias,
+                RegionKind::Remove => self.remove_bias,
+            };
+            for y in 0..height {
+                for x in 0..width {
+                    if region.shape.contains(x, y) {
+                        bias[y as usize][x as usize] = value;
+                    }
+                }
+            }
+        }
+        bias
+    }
+}
+
+fn parse_list<T: FromStr>(s: &str) -> Result<Vec<T>> {
+    s.split(',')
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .ok()
+                .with_context(|| format!("invalid number: {value}"))
+        })
+        .collect()
+}
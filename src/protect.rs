@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+
+use image::{DynamicImage, GenericImageView};
+
+use crate::energy::EnergyModifier;
+
+/// Protects regions of skin, and with them most faces, without relying on
+/// any trained model.
+///
+/// Pixels are classified by their chroma in YCbCr, then connected regions
+/// that are too small or too sparse to be a person are thrown away.
+#[derive(Clone, Copy, Debug)]
+pub struct SkinProtector {
+    /// The energy added to each pixel of a skin region.
+    pub bias: i32,
+    /// The smallest region kept, as a fraction of the image's area.
+    pub min_region_fraction: f32,
+    /// The smallest share of a region's bounding box that it has to fill.
+    pub min_fill_ratio: f32,
+}
+
+impl Default for SkinProtector {
+    fn default() -> Self {
+        Self {
+            bias: 200_000,
+            min_region_fraction: 0.001,
+            min_fill_ratio: 0.25,
+        }
+    }
+}
+
+impl SkinProtector {
+    /// Finds the regions of skin in an image as rows of flags.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate image;
+    /// # extern crate rmr;
+    /// # use image::{DynamicImage, GenericImage, Rgba};
+    /// # use rmr::protect::SkinProtector;
+    /// # fn main() {
+    /// let mut image = DynamicImage::new_rgba8(100, 100);
+    /// for y in 0..100 {
+    ///     for x in 0..100 {
+    ///         image.put_pixel(x, y, Rgba([40, 40, 40, 255]));
+    ///     }
+    /// }
+    /// for y in 20..60 {
+    ///     for x in 30..60 {
+    ///         image.put_pixel(x, y, Rgba([224, 172, 140, 255]));
+    ///     }
+    /// }
+    /// // A single skin coloured speck is too small to be a person.
+    /// image.put_pixel(90, 90, Rgba([224, 172, 140, 255]));
+    ///
+    /// let skin = SkinProtector::default().find_skin(&image);
+    /// assert!(skin[40][45]);
+    /// assert!(!skin[10][10]);
+    /// assert!(!skin[90][90]);
+    /// # }
+    /// ```
+    pub fn find_skin(&self, image: &DynamicImage) -> Vec<Vec<bool>> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let mut skin = vec![vec![false; width]; height];
+        for (x, y, pixel) in image.pixels() {
+            skin[y as usize][x as usize] = is_skin_tone(pixel.0);
+        }
+
+        let min_size = (self.min_region_fraction * (width * height) as f32).ceil() as usize;
+        let mut seen = vec![vec![false; width]; height];
+        let mut kept = vec![vec![false; width]; height];
+        for y in 0..height {
+            for x in 0..width {
+                if !skin[y][x] || seen[y][x] {
+                    continue;
+                }
+                let region = flood_fill(&skin, &mut seen, x, y);
+                if region.len() >= min_size.max(1) && fill_ratio(&region) >= self.min_fill_ratio {
+                    for (x, y) in region {
+                        kept[y][x] = true;
+                    }
+                }
+            }
+        }
+        kept
+    }
+}
+
+impl EnergyModifier for SkinProtector {
+    fn bias(&self, image: &DynamicImage) -> Vec<Vec<i32>> {
+        self.find_skin(image)
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|skin| if skin { self.bias } else { 0 })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Classifies a pixel using the Cb and Cr ranges from Chai and Ngan. Very
+/// dark, very bright and transparent pixels are never skin.
+fn is_skin_tone([r, g, b, a]: [u8; 4]) -> bool {
+    if a == 0 {
+        return false;
+    }
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let cb = 128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b;
+    let cr = 128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b;
+    (40.0..=250.0).contains(&y) && (77.0..=127.0).contains(&cb) && (133.0..=173.0).contains(&cr)
+}
+
+/// Collects the 4-connected region of set flags starting at the given point.
+fn flood_fill(
+    flags: &[Vec<bool>],
+    seen: &mut [Vec<bool>],
+    x: usize,
+    y: usize,
+) -> Vec<(usize, usize)> {
+    let (width, height) = (flags[0].len(), flags.len());
+    let mut region = vec![];
+    let mut queue = VecDeque::from([(x, y)]);
+    seen[y][x] = true;
+    while let Some((x, y)) = queue.pop_front() {
+        region.push((x, y));
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        for (x, y) in neighbours {
+            if x < width && y < height && flags[y][x] && !seen[y][x] {
+                seen[y][x] = true;
+                queue.push_back((x, y));
+            }
+        }
+    }
+    region
+}
+
+/// The share of its bounding box that a region covers.
+fn fill_ratio(region: &[(usize, usize)]) -> f32 {
+    let (mut min_x, mut min_y) = (usize::MAX, usize::MAX);
+    let (mut max_x, mut max_y) = (0, 0);
+    for &(x, y) in region {
+        min_x = min_x.min(x);
+        min_y = min_y.min(y);
+        max_x = max_x.max(x);
+        max_y = max_y.max(y);
+    }
+    let area = (max_x - min_x + 1) * (max_y - min_y + 1);
+    region.len() as f32 / area as f32
+}
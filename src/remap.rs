@@ -0,0 +1,180 @@
+use crate::mask::Rect;
+
+/// Maps coordinates between an image and the result of carving it, for
+/// moving keypoints and bounding boxes through a resize and back.
+///
+/// Seam carving doesn't move pixels uniformly, so this is built from where
+/// every pixel of the carved image came from. Any scaling done after carving
+/// is accounted for as well.
+#[derive(Clone, Debug)]
+pub struct CarveMap {
+    source_size: (usize, usize),
+    carved_size: (usize, usize),
+    output_size: (usize, usize),
+    /// Where each pixel of the carved image came from, row by row.
+    sources: Vec<(usize, usize)>,
+    /// Where each source pixel ended up in the carved image, row by row.
+    targets: Vec<Option<(usize, usize)>>,
+}
+
+impl CarveMap {
+    /// Creates a map from the source position of each pixel of the carved
+    /// image. Pixels that growing duplicated map to their first copy.
+    pub fn new(
+        sources: Vec<Vec<(usize, usize)>>,
+        source_size: (usize, usize),
+        output_size: (usize, usize),
+    ) -> Self {
+        let carved_size = (sources.first().map_or(0, |row| row.len()), sources.len());
+        let (source_width, source_height) = source_size;
+        let mut targets = vec![None; source_width * source_height];
+        for (y, row) in sources.iter().enumerate() {
+            for (x, &(sx, sy)) in row.iter().enumerate() {
+                let target = &mut targets[sy * source_width + sx];
+                if target.is_none() {
+                    *target = Some((x, y));
+                }
+            }
+        }
+        Self {
+            source_size,
+            carved_size,
+            output_size,
+            sources: sources.into_iter().flatten().collect(),
+            targets,
+        }
+    }
+
+    pub fn source_size(&self) -> (usize, usize) {
+        self.source_size
+    }
+
+    pub fn output_size(&self) -> (usize, usize) {
+        self.output_size
+    }
+
+    /// Finds where a point of the source image ended up in the output.
+    /// Removed pixels go to the nearest surviving pixel in their row, or in
+    /// their column if the whole row is gone. Returns `None` for points
+    /// outside the source image.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rmr;
+    /// # use rmr::remap::CarveMap;
+    /// # fn main() {
+    /// // The middle column of a 3x1 image was carved away.
+    /// let map = CarveMap::new(vec![vec![(0, 0), (2, 0)]], (3, 1), (2, 1));
+    /// assert_eq!(Some((1, 0)), map.forward(2, 0));
+    /// assert_eq!(Some((0, 0)), map.forward(1, 0));
+    /// assert_eq!(None, map.forward(3, 0));
+    /// assert_eq!((2, 0), map.inverse(1, 0));
+    ///
+    /// let empty = CarveMap::new(vec![], (3, 1), (0, 0));
+    /// assert_eq!(None, empty.forward(1, 0));
+    /// # }
+    /// ```
+    pub fn forward(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let (width, height) = self.source_size;
+        if x >= width || y >= height {
+            return None;
+        }
+        let target = |x: usize, y: usize| self.targets[y * width + x];
+        let (cx, cy) =
+            nearest(x, width, |x| target(x, y)).or_else(|| nearest(y, height, |y| target(x, y)))?;
+        Some((
+            scale(cx, self.carved_size.0, self.output_size.0),
+            scale(cy, self.carved_size.1, self.output_size.1),
+        ))
+    }
+
+    /// Finds where a point of the output came from in the source image.
+    /// Points outside the output are clamped to its edges.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the output is empty, as there is no point to clamp to.
+    pub fn inverse(&self, x: usize, y: usize) -> (usize, usize) {
+        let (output_width, output_height) = self.output_size;
+        let (carved_width, carved_height) = self.carved_size;
+        assert!(
+            output_width > 0 && output_height > 0 && !self.sources.is_empty(),
+            "no point of an empty output came from the source"
+        );
+        let cx = scale(x.min(output_width - 1), output_width, carved_width);
+        let cy = scale(y.min(output_height - 1), output_height, carved_height);
+        self.sources[cy * carved_width + cx]
+    }
+
+    /// Maps a box in the source image to the smallest box in the output that
+    /// holds everything left of it. Returns `None` if it was carved away.
+    pub fn forward_rect(&self, rect: &Rect) -> Option<Rect> {
+        let (width, height) = self.source_size;
+        let points = rect_points(rect, (width, height)).filter_map(|(x, y)| {
+            let (cx, cy) = self.targets[y * width + x]?;
+            Some((
+                scale(cx, self.carved_size.0, self.output_size.0),
+                scale(cy, self.carved_size.1, self.output_size.1),
+            ))
+        });
+        bounding_rect(points)
+    }
+
+    /// Maps a box in the output to the smallest box in the source image that
+    /// holds everything it came from.
+    pub fn inverse_rect(&self, rect: &Rect) -> Option<Rect> {
+        let points = rect_points(rect, self.output_size).map(|(x, y)| self.inverse(x, y));
+        bounding_rect(points)
+    }
+}
+
+/// Scales a pixel coordinate between two lengths by its centre.
+fn scale(value: usize, from: usize, to: usize) -> usize {
+    if from == to {
+        return value;
+    }
+    ((2 * value + 1) * to / (2 * from)).min(to - 1)
+}
+
+/// Searches outwards from `start` for the closest index with a value,
+/// preferring lower indices on ties.
+fn nearest<T>(start: usize, len: usize, get: impl Fn(usize) -> Option<T>) -> Option<T> {
+    (0..len).find_map(|distance| {
+        let before = start.checked_sub(distance).and_then(&get);
+        before.or_else(|| {
+            let after = start + distance;
+            (after < len).then(|| get(after)).flatten()
+        })
+    })
+}
+
+fn rect_points(
+    rect: &Rect,
+    (width, height): (usize, usize),
+) -> impl Iterator<Item = (usize, usize)> {
+    let left = (rect.x as usize).min(width);
+    let top = (rect.y as usize).min(height);
+    let right = (rect.x as usize + rect.width as usize).min(width);
+    let bottom = (rect.y as usize + rect.height as usize).min(height);
+    (top..bottom).flat_map(move |y| (left..right).map(move |x| (x, y)))
+}
+
+fn bounding_rect(points: impl Iterator<Item = (usize, usize)>) -> Option<Rect> {
+    let (mut left, mut top) = (usize::MAX, usize::MAX);
+    let (mut right, mut bottom) = (0, 0);
+    let mut any = false;
+    for (x, y) in points {
+        any = true;
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x);
+        bottom = bottom.max(y);
+    }
+    any.then(|| Rect {
+        x: left as u32,
+        y: top as u32,
+        width: (right - left + 1) as u32,
+        height: (bottom - top + 1) as u32,
+    })
+}
@@ -0,0 +1,186 @@
+use std::f32::consts::PI;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageBuffer, Luma};
+
+/// The size of the square the spectral residual is computed at. Saliency is
+/// a coarse property, so a small image is both faster and more reliable.
+const SALIENCY_SIZE: usize = 64;
+
+/// How far the saliency map is blurred, in pixels at `SALIENCY_SIZE`.
+const SALIENCY_BLUR: f32 = 2.5;
+
+type SaliencyBuffer = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Estimates which parts of an image stand out using the spectral residual
+/// method from Hou and Zhang. Returns rows of values from 0 to 1 that are
+/// the same size as the image.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate image;
+/// # extern crate rmr;
+/// # use image::{DynamicImage, GenericImage, Rgba};
+/// # use rmr::saliency::spectral_residual;
+/// # fn main() {
+/// let mut image = DynamicImage::new_rgba8(128, 128);
+/// for y in 0..128 {
+///     for x in 0..128 {
+///         image.put_pixel(x, y, Rgba([40, 40, 40, 255]));
+///     }
+/// }
+/// for y in 80..90 {
+///     for x in 20..30 {
+///         image.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+///     }
+/// }
+///
+/// let saliency = spectral_residual(&image);
+/// assert!(saliency[85][25] > 0.5);
+/// assert!(saliency[20][100] < 0.1);
+/// # }
+/// ```
+pub fn spectral_residual(image: &DynamicImage) -> Vec<Vec<f32>> {
+    let small = image
+        .resize_exact(
+            SALIENCY_SIZE as u32,
+            SALIENCY_SIZE as u32,
+            FilterType::Triangle,
+        )
+        .into_luma8();
+    let mut spectrum: Vec<Complex> = small
+        .pixels()
+        .map(|pixel| Complex::new(pixel.0[0] as f32, 0.0))
+        .collect();
+    fft_2d(&mut spectrum, SALIENCY_SIZE, false);
+
+    // The residual is what is left of the log amplitude after removing its
+    // local average, which is where the unexpected parts of the image are.
+    let log_amplitude: Vec<f32> = spectrum.iter().map(|c| (c.norm() + 1.0).ln()).collect();
+    let average = box_filter(&log_amplitude, SALIENCY_SIZE);
+    for (i, c) in spectrum.iter_mut().enumerate() {
+        let phase = c.im.atan2(c.re);
+        let magnitude = (log_amplitude[i] - average[i]).exp();
+        *c = Complex::new(magnitude * phase.cos(), magnitude * phase.sin());
+    }
+    fft_2d(&mut spectrum, SALIENCY_SIZE, true);
+
+    let size = SALIENCY_SIZE as u32;
+    let map: SaliencyBuffer = ImageBuffer::from_fn(size, size, |x, y| {
+        let c = spectrum[y as usize * SALIENCY_SIZE + x as usize];
+        Luma([c.re * c.re + c.im * c.im])
+    });
+    let map = imageops::blur(&map, SALIENCY_BLUR);
+    let map = imageops::resize(&map, image.width(), image.height(), FilterType::Triangle);
+
+    let max = map.pixels().map(|p| p.0[0]).fold(0.0, f32::max);
+    map.rows()
+        .map(|row| {
+            row.map(|p| if max > 0.0 { p.0[0] / max } else { 0.0 })
+                .collect()
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    fn norm(&self) -> f32 {
+        (self.re * self.re + self.im * self.im).sqrt()
+    }
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+/// Transforms each row and then each column of a square buffer.
+fn fft_2d(data: &mut [Complex], size: usize, inverse: bool) {
+    for row in data.chunks_mut(size) {
+        fft(row, inverse);
+    }
+    let mut column = vec![Complex::new(0.0, 0.0); size];
+    for x in 0..size {
+        for y in 0..size {
+            column[y] = data[y * size + x];
+        }
+        fft(&mut column, inverse);
+        for y in 0..size {
+            data[y * size + x] = column[y];
+        }
+    }
+}
+
+/// An in-place radix-2 fast Fourier transform. The length of `data` must be
+/// a power of two.
+fn fft(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+
+    // Reorder by bit reversed index so the butterflies can work in place.
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let twiddle = Complex::new((angle * k as f32).cos(), (angle * k as f32).sin());
+                let a = data[start + k];
+                let b = data[start + k + len / 2].mul(twiddle);
+                data[start + k] = Complex::new(a.re + b.re, a.im + b.im);
+                data[start + k + len / 2] = Complex::new(a.re - b.re, a.im - b.im);
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        for c in data.iter_mut() {
+            *c = Complex::new(c.re / n as f32, c.im / n as f32);
+        }
+    }
+}
+
+/// Averages each value with its neighbours in a 3x3 square, clamped to the
+/// edges.
+fn box_filter(values: &[f32], size: usize) -> Vec<f32> {
+    let mut averages = vec![0.0; values.len()];
+    for y in 0..size {
+        for x in 0..size {
+            let mut sum = 0.0;
+            let mut count = 0;
+            for ny in y.saturating_sub(1)..(y + 2).min(size) {
+                for nx in x.saturating_sub(1)..(x + 2).min(size) {
+                    sum += values[ny * size + nx];
+                    count += 1;
+                }
+            }
+            averages[y * size + x] = sum / count as f32;
+        }
+    }
+    averages
+}
@@ -0,0 +1,194 @@
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+
+use crate::carve::Direction;
+
+/// Whether a seam was taken out of an image or added to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum SeamKind {
+    Remove,
+    Insert,
+}
+
+impl FromStr for SeamKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "remove" => Ok(SeamKind::Remove),
+            "insert" => Ok(SeamKind::Insert),
+            _ => bail!("unknown seam kind: {s}"),
+        }
+    }
+}
+
+/// A seam carved out of or into an image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Seam {
+    pub direction: Direction,
+    pub kind: SeamKind,
+    /// The position of the seam in the order seams were carved.
+    pub index: usize,
+    /// The seam's path cost, which is its total energy plus any penalty for
+    /// drifting sideways.
+    pub cost: u32,
+    /// One point per row for vertical seams or per column for horizontal
+    /// ones, in the coordinates of the image just before this seam was
+    /// carved. Removed seams point at the pixels taken out and inserted
+    /// seams at the new pixels, so seams can be applied in order.
+    pub points: Vec<(usize, usize)>,
+}
+
+/// The formats seams can be exported in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SeamFormat {
+    #[default]
+    Json,
+    /// One line per point with the columns `index,direction,kind,cost,x,y`.
+    Csv,
+}
+
+impl SeamFormat {
+    /// Picks CSV for paths ending in `.csv` and JSON for anything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => SeamFormat::Csv,
+            _ => SeamFormat::Json,
+        }
+    }
+}
+
+impl FromStr for SeamFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(SeamFormat::Json),
+            "csv" => Ok(SeamFormat::Csv),
+            _ => bail!("unknown seam format: {s}"),
+        }
+    }
+}
+
+/// Writes seams in the given format.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate rmr;
+/// # use rmr::carve::Direction;
+/// # use rmr::seam::{write_seams, Seam, SeamFormat, SeamKind};
+/// # fn main() {
+/// let seam = Seam {
+///     direction: Direction::Vertical,
+///     kind: SeamKind::Remove,
+///     index: 0,
+///     cost: 12,
+///     points: vec![(3, 0), (4, 1)],
+/// };
+/// let mut csv = vec![];
+/// write_seams(&[seam], &mut csv, SeamFormat::Csv).unwrap();
+/// assert_eq!(
+///     "index,direction,kind,cost,x,y\n0,vertical,remove,12,3,0\n0,vertical,remove,12,4,1\n",
+///     String::from_utf8(csv).unwrap()
+/// );
+/// # }
+/// ```
+pub fn write_seams<W: Write>(seams: &[Seam], mut writer: W, format: SeamFormat) -> Result<()> {
+    match format {
+        SeamFormat::Json => write_json(seams, &mut writer)?,
+        SeamFormat::Csv => {
+            writeln!(writer, "index,direction,kind,cost,x,y")?;
+            for seam in seams {
+                let direction = match seam.direction {
+                    Direction::Vertical => "vertical",
+                    Direction::Horizontal => "horizontal",
+                };
+                let kind = match seam.kind {
+                    SeamKind::Remove => "remove",
+                    SeamKind::Insert => "insert",
+                };
+                for (x, y) in &seam.points {
+                    writeln!(
+                        writer,
+                        "{},{direction},{kind},{},{x},{y}",
+                        seam.index, seam.cost
+                    )?;
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads seams written by [`write_seams`].
+pub fn read_seams<R: BufRead>(reader: R, format: SeamFormat) -> Result<Vec<Seam>> {
+    match format {
+        SeamFormat::Json => read_json(reader),
+        SeamFormat::Csv => read_csv(reader),
+    }
+}
+
+fn read_csv<R: BufRead>(reader: R) -> Result<Vec<Seam>> {
+    let mut seams: Vec<Seam> = vec![];
+    for (number, line) in reader.lines().enumerate().skip(1) {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let [index, direction, kind, cost, x, y] = fields[..] else {
+            bail!("expected 6 fields on line {}", number + 1);
+        };
+        let parse = |value: &str| -> Result<usize> {
+            value
+                .parse()
+                .with_context(|| format!("invalid number on line {}: {value}", number + 1))
+        };
+        let index = parse(index)?;
+        let point = (parse(x)?, parse(y)?);
+        match seams.last_mut() {
+            Some(seam) if seam.index == index => seam.points.push(point),
+            _ => seams.push(Seam {
+                direction: direction.parse()?,
+                kind: kind.parse()?,
+                index,
+                cost: parse(cost)? as u32,
+                points: vec![point],
+            }),
+        }
+    }
+    Ok(seams)
+}
+
+#[cfg(feature = "serde")]
+fn read_json<R: BufRead>(reader: R) -> Result<Vec<Seam>> {
+    serde_json::from_reader(reader).context("invalid seams")
+}
+
+#[cfg(not(feature = "serde"))]
+fn read_json<R: BufRead>(_reader: R) -> Result<Vec<Seam>> {
+    bail!("reading seams as JSON requires the serde feature")
+}
+
+#[cfg(feature = "serde")]
+fn write_json<W: Write>(seams: &[Seam], writer: &mut W) -> Result<()> {
+    serde_json::to_writer(&mut *writer, seams)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_json<W: Write>(_seams: &[Seam], _writer: &mut W) -> Result<()> {
+    bail!("writing seams as JSON requires the serde feature")
+}
@@ -0,0 +1,275 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use image::{DynamicImage, GenericImageView};
+
+use crate::carve::Carver;
+use crate::energy::EnergyModifier;
+
+/// The path of each frame in a numbered sequence, such as
+/// `frames/frame_%04d.png`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FramePattern {
+    prefix: String,
+    suffix: String,
+    width: usize,
+}
+
+impl FramePattern {
+    /// Fills the frame number into the pattern.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate rmr;
+    /// # use std::path::PathBuf;
+    /// # use rmr::video::FramePattern;
+    /// # fn main() {
+    /// let pattern: FramePattern = "out/frame_%04d.png".parse().unwrap();
+    /// assert_eq!(PathBuf::from("out/frame_0012.png"), pattern.path(12));
+    /// # }
+    /// ```
+    pub fn path(&self, number: usize) -> PathBuf {
+        let width = self.width;
+        format!("{}{number:0width$}{}", self.prefix, self.suffix).into()
+    }
+
+    /// Finds the numbers of the frames that exist, starting from 0 or 1 and
+    /// stopping at the first gap.
+    pub fn find_frames(&self) -> Vec<usize> {
+        let start = if self.path(0).exists() { 0 } else { 1 };
+        (start..)
+            .take_while(|&number| self.path(number).exists())
+            .collect()
+    }
+}
+
+impl FromStr for FramePattern {
+    type Err = Error;
+
+    /// Parses a pattern with a single `%d`, optionally padded as in `%04d`.
+    fn from_str(s: &str) -> Result<Self> {
+        let Some(start) = s.find('%') else {
+            bail!("expected a frame number such as %04d in {s}");
+        };
+        let rest = &s[start + 1..];
+        let Some(end) = rest.find('d') else {
+            bail!("expected a frame number such as %04d in {s}");
+        };
+        let width = match &rest[..end] {
+            "" => 0,
+            digits => digits
+                .parse()
+                .map_err(|_| Error::msg(format!("invalid frame number width in {s}")))?,
+        };
+        let suffix = &rest[end + 1..];
+        if suffix.contains('%') {
+            bail!("expected a single frame number in {s}");
+        }
+        Ok(Self {
+            prefix: s[..start].to_owned(),
+            suffix: suffix.to_owned(),
+            width,
+        })
+    }
+}
+
+/// Makes pixels more expensive the further they are from the pixels that
+/// were carved in the previous frame, so seams stay where they were unless
+/// the content gives a good reason to move.
+#[derive(Clone, Debug)]
+pub struct TemporalGuide {
+    /// The distance of each pixel to the nearest carved pixel in its row or
+    /// column, capped at the radius.
+    distances: Vec<Vec<usize>>,
+    /// The energy added per pixel of distance.
+    pub coherence: i32,
+}
+
+impl TemporalGuide {
+    /// Creates a guide from the points a carve removed, in the coordinates
+    /// of a frame of the given size.
+    pub fn new(
+        removed_points: &[(usize, usize)],
+        (width, height): (usize, usize),
+        radius: usize,
+        coherence: i32,
+    ) -> Self {
+        let mut distances = vec![vec![radius; width]; height];
+        for &(x, y) in removed_points {
+            for d in 0..=radius {
+                let around = [
+                    (x.wrapping_sub(d), y),
+                    (x + d, y),
+                    (x, y.wrapping_sub(d)),
+                    (x, y + d),
+                ];
+                for (x, y) in around {
+                    if x < width && y < height {
+                        distances[y][x] = distances[y][x].min(d);
+                    }
+                }
+            }
+        }
+        Self {
+            distances,
+            coherence,
+        }
+    }
+}
+
+impl EnergyModifier for TemporalGuide {
+    fn bias(&self, image: &DynamicImage) -> Vec<Vec<i32>> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let distance = self.distances.get(y).and_then(|row| row.get(x));
+                        let distance = distance.copied().unwrap_or(0) as i32;
+                        distance.saturating_mul(self.coherence)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// The coherence a [`TemporalCarver`] uses unless told otherwise.
+pub const DEFAULT_COHERENCE: i32 = 2_000;
+
+/// The radius a [`TemporalCarver`] uses unless told otherwise.
+pub const DEFAULT_COHERENCE_RADIUS: usize = 8;
+
+/// Carves the frames of a video one after another without the seams
+/// flickering between frames.
+#[derive(Clone, Debug)]
+pub struct TemporalCarver {
+    /// The energy added per pixel a seam moves from where it was in the
+    /// previous frame. Zero carves every frame independently.
+    pub coherence: i32,
+    /// How far from the previous frame's seams the penalty stops growing.
+    pub radius: usize,
+    previous: Option<TemporalGuide>,
+}
+
+impl Default for TemporalCarver {
+    fn default() -> Self {
+        Self {
+            coherence: DEFAULT_COHERENCE,
+            radius: DEFAULT_COHERENCE_RADIUS,
+            previous: None,
+        }
+    }
+}
+
+impl TemporalCarver {
+    pub fn new(coherence: i32, radius: usize) -> Self {
+        Self {
+            coherence,
+            radius,
+            previous: None,
+        }
+    }
+
+    /// Adds the guide from the previous frame to a carver made for the next
+    /// one. The first frame is left alone.
+    pub fn guide(&self, carver: Carver) -> Carver {
+        match &self.previous {
+            Some(guide) if self.coherence > 0 => carver.with_energy_modifier(guide),
+            _ => carver,
+        }
+    }
+
+    /// Remembers where a frame was carved, to guide the next one.
+    pub fn follow(&mut self, carver: &Carver) {
+        self.previous = Some(TemporalGuide::new(
+            carver.get_removed_points(),
+            carver.get_source_size(),
+            self.radius,
+            self.coherence,
+        ));
+    }
+
+    /// Resizes the next frame with a carver made for it, guided by the seams
+    /// of the frame before.
+    pub fn carve_frame(&mut self, carver: Carver, width: usize, height: usize) -> DynamicImage {
+        let mut carver = self.guide(carver);
+        let image = carver.resize(width, height);
+        self.follow(&carver);
+        image
+    }
+}
+
+/// Raises the energy of each pixel of one frame of an animation to the
+/// highest energy it has in any frame, so seams shared by every frame avoid
+/// whatever moves through the animation.
+#[derive(Clone, Debug)]
+pub struct FrameEnergy {
+    bias: Vec<Vec<i32>>,
+}
+
+impl FrameEnergy {
+    /// Creates the modifier from the energy maps of every frame, starting
+    /// with the frame that is carved.
+    pub fn new(energy_maps: &[Vec<Vec<u32>>]) -> Self {
+        let Some((first, others)) = energy_maps.split_first() else {
+            return Self { bias: vec![] };
+        };
+        let bias = first
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(x, &energy)| {
+                        let highest = others.iter().map(|map| map[y][x]).max().unwrap_or(0);
+                        highest.saturating_sub(energy).min(i32::MAX as u32) as i32
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { bias }
+    }
+}
+
+impl EnergyModifier for FrameEnergy {
+    fn bias(&self, image: &DynamicImage) -> Vec<Vec<i32>> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let bias = self.bias.get(y).and_then(|row| row.get(x));
+                        bias.copied().unwrap_or(0)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Makes a carver for the first frame of an animation whose seams suit every
+/// frame, using `make_carver` for the energy of each. Replaying its seams
+/// with [`Carver::replay`] carves the other frames to match.
+pub fn make_animation_carver(
+    frames: &[DynamicImage],
+    make_carver: impl Fn(&DynamicImage) -> Result<Carver>,
+) -> Result<Carver> {
+    let Some(first) = frames.first() else {
+        bail!("an animation needs at least one frame");
+    };
+    if frames
+        .iter()
+        .any(|frame| frame.dimensions() != first.dimensions())
+    {
+        bail!("every frame of an animation has to be the same size");
+    }
+    let energy_maps = frames
+        .iter()
+        .map(|frame| Ok(make_carver(frame)?.get_energy_map()))
+        .collect::<Result<Vec<_>>>()?;
+    let carver = make_carver(first)?.with_energy_modifier(&FrameEnergy::new(&energy_maps));
+    Ok(carver)
+}
@@ -1,8 +1,23 @@
+use std::cell::RefCell;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use image::{DynamicImage, GenericImageView, RgbImage};
+use image::{
+    Delay, DynamicImage, Frame, GenericImage, GenericImageView, ImageFormat, RgbImage, Rgba,
+};
 
-use rmr::carve::Carver;
+use rmr::carve::{Cancelled, Carver, Direction, Hybrid, SearchMode, Stiffness, Strategy};
+use rmr::codec::{read_input, write_animation, Input};
+use rmr::color::ColorSpace;
+use rmr::compact::CompactCarver;
+use rmr::energy::{EnergyFunction, EnergyOptions};
+use rmr::layer::{Interpolation, Layer};
+use rmr::mask::{Mask, Rect, Region, RegionKind, Shape};
+use rmr::protect::SkinProtector;
+use rmr::seam::{read_seams, write_seams, SeamFormat, SeamKind};
+use rmr::video::{make_animation_carver, TemporalCarver};
 
 static INPUT: &[u8] = include_bytes!("images/input.png");
 static WIDTH_MINUS_FIVE: &[u8] = include_bytes!("images/out-width-minus-five.png");
@@ -63,6 +78,780 @@ fn carver_both_plus_five_test() {
     test_carve!(BOTH_PLUS_FIVE, 5, 5);
 }
 
+#[test]
+fn carver_seams_per_pass_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let mut carver = Carver::new(&input).with_seams_per_pass(4);
+    let output = carver.resize(width as usize - 10, height as usize - 10);
+
+    assert_eq!((width - 10, height - 10), output.dimensions());
+    assert_eq!(
+        20 * height as usize - 100,
+        carver.get_removed_points().len()
+    );
+}
+
+#[test]
+fn carver_hybrid_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let mut carver = Carver::new(&input).with_hybrid(Hybrid {
+        max_carve_fraction: 0.5,
+        ..Hybrid::default()
+    });
+    let output = carver.resize(width as usize - 10, height as usize + 10);
+
+    assert_eq!((width - 10, height + 10), output.dimensions());
+    let report = carver.get_report();
+    assert_eq!((5, 5), report.carved);
+    assert_eq!((5, 5), report.scaled);
+}
+
+#[test]
+fn carver_hybrid_max_seam_cost_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let mut carver = Carver::new(&input).with_hybrid(Hybrid {
+        max_seam_cost: Some(0),
+        max_carve_fraction: 1.0,
+        ..Hybrid::default()
+    });
+    let output = carver.resize(width as usize - 10, height as usize);
+
+    assert_eq!((width - 10, height), output.dimensions());
+    let report = carver.get_report();
+    assert_eq!(10, report.carved.0 + report.scaled.0);
+    assert!(report.scaled.0 > 0);
+}
+
+#[test]
+fn carver_crop_strategy_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let mut carver = Carver::new(&input).with_strategy(Strategy::Crop);
+    let output = carver.resize(width as usize - 10, height as usize + 5);
+
+    assert_eq!((width - 10, height + 5), output.dimensions());
+    let report = carver.get_report();
+    assert_eq!((10, 0), report.cropped);
+    assert_eq!((0, 5), report.carved);
+}
+
+#[test]
+fn carver_auto_strategy_test() {
+    // Every seam through stripes three pixels apart joins two stripes that
+    // were apart, so carving ends up taking the columns with the most
+    // energy, while cropping takes whatever is at the edges.
+    let stripes = RgbImage::from_fn(12, 20, |x, _| image::Rgb([120 * (x % 3) as u8; 3]));
+    let mut carver = Carver::new(&DynamicImage::ImageRgb8(stripes)).with_strategy(Strategy::Auto);
+    let output = carver.resize(7, 20);
+    assert_eq!((7, 20), output.dimensions());
+    let report = carver.get_report();
+    assert_eq!((5, 0), report.cropped);
+    assert_eq!((0, 0), report.carved);
+
+    // A flat band in the middle of stripes three pixels apart can be carved
+    // for nothing, while cropping has to cut into the stripes at the edges.
+    let banded = RgbImage::from_fn(40, 20, |x, _| match x {
+        10..20 => image::Rgb([90, 90, 90]),
+        _ => image::Rgb([120 * (x % 3) as u8; 3]),
+    });
+    let mut carver = Carver::new(&DynamicImage::ImageRgb8(banded)).with_strategy(Strategy::Auto);
+    let output = carver.resize(35, 20);
+    assert_eq!((35, 20), output.dimensions());
+    let report = carver.get_report();
+    assert_eq!((0, 0), report.cropped);
+    assert_eq!((5, 0), report.carved);
+}
+
+#[test]
+fn carver_window_energy_test() {
+    // Faint noise on the left, which the gradient finds cheaper to carve than
+    // the stronger but regular stripes on the right. Window energies see the
+    // noise as texture and carve the stripes instead.
+    let image = RgbImage::from_fn(40, 24, |x, y| match x {
+        0..20 => {
+            let mut noise = x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263);
+            noise = (noise ^ (noise >> 13)).wrapping_mul(1_274_126_177);
+            image::Rgb([100 + ((noise ^ (noise >> 16)) % 24) as u8; 3])
+        }
+        _ => image::Rgb([100 + 12 * (x % 3) as u8; 3]),
+    });
+    let input = DynamicImage::ImageRgb8(image);
+    let carved_texture = |function| {
+        let mut carver = Carver::new(&input).with_energy_options(EnergyOptions {
+            function,
+            ..EnergyOptions::default()
+        });
+        let output = carver.resize(30, 24);
+        assert_eq!((30, 24), output.dimensions());
+        let removed = carver.get_removed_points();
+        removed.iter().filter(|&&(x, _)| x < 20).count() * 100 / removed.len()
+    };
+
+    assert!(carved_texture(EnergyFunction::Gradient) > 50);
+    assert!(carved_texture(EnergyFunction::Entropy { radius: 4 }) < 10);
+    assert!(carved_texture(EnergyFunction::Hog { radius: 5 }) < 10);
+}
+
+#[test]
+fn carver_protect_skin_test() {
+    // A busy background makes the flat block the cheapest place to carve.
+    let skin = Rgba([224, 172, 140, 255]);
+    let mut input = DynamicImage::new_rgba8(60, 40);
+    for y in 0..40 {
+        for x in 0..60 {
+            let shade = ((x * 7919 + y * 104729) % 251) as u8;
+            input.put_pixel(x, y, Rgba([shade, shade, shade, 255]));
+        }
+    }
+    for y in 10..30 {
+        for x in 20..40 {
+            input.put_pixel(x, y, skin);
+        }
+    }
+
+    let mut carver = Carver::new(&input).with_energy_modifier(&SkinProtector::default());
+    let output = carver.resize(45, 40);
+
+    let skin_pixels = output.pixels().filter(|&(_, _, p)| p == skin).count();
+    assert_eq!(400, skin_pixels);
+}
+
+#[test]
+fn carver_remove_rect_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let mask = Mask::new(vec![Region {
+        kind: RegionKind::Remove,
+        shape: Shape::Rect(Rect {
+            x: 20,
+            y: 0,
+            width: 5,
+            height,
+        }),
+    }]);
+    let mut carver = Carver::new(&input).with_energy_modifier(&mask);
+    carver.resize(width as usize - 5, height as usize);
+
+    assert!(carver
+        .get_removed_points()
+        .iter()
+        .all(|&(x, _)| (20..25).contains(&x)));
+}
+
+#[test]
+fn carver_remove_marked_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let mask = Mask::new(vec![Region {
+        kind: RegionKind::Remove,
+        shape: Shape::Rect(Rect {
+            x: 20,
+            y: 40,
+            width: 30,
+            height: 4,
+        }),
+    }]);
+    let mut carver = Carver::new(&input).with_energy_modifier(&mask);
+    let output = carver.clone().remove_marked(false);
+    assert_eq!((width, height - 4), output.dimensions());
+
+    let output = carver.remove_marked(true);
+    assert_eq!((width, height), output.dimensions());
+}
+
+#[test]
+fn carver_remove_marked_busy_test() {
+    // A checkerboard of 2x2 red and green squares has about the highest
+    // energy there is, which a weak removal bias barely dents, in an image
+    // that is otherwise flat and costs nothing to carve. Its Lab gradient
+    // is higher than any RGB gradient.
+    let image = RgbImage::from_fn(30, 20, |x, y| match (x, (x / 2 + y / 2) % 2) {
+        (10..14, 0) => image::Rgb([255, 0, 0]),
+        (10..14, _) => image::Rgb([0, 255, 0]),
+        _ => image::Rgb([128; 3]),
+    });
+    let input = DynamicImage::ImageRgb8(image);
+    let mask = Mask {
+        remove_bias: -1,
+        ..Mask::new(vec![Region {
+            kind: RegionKind::Remove,
+            shape: Shape::Rect(Rect {
+                x: 10,
+                y: 0,
+                width: 4,
+                height: 20,
+            }),
+        }])
+    };
+
+    for color_space in [ColorSpace::Rgb, ColorSpace::Lab76] {
+        let energy_options = EnergyOptions {
+            color_space,
+            ..EnergyOptions::default()
+        };
+        let mut carver = Carver::new(&input)
+            .with_energy_options(energy_options)
+            .with_energy_modifier(&mask);
+        let output = carver.remove_marked(false);
+        assert_eq!((26, 20), output.dimensions(), "{color_space:?}");
+        assert!(carver
+            .get_removed_points()
+            .iter()
+            .all(|&(x, _)| (10..14).contains(&x)));
+    }
+}
+
+#[test]
+fn carver_find_seams_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+    let carver = Carver::new(&input);
+
+    let seams = carver.find_seams(Direction::Vertical, 3);
+    assert_eq!(3, seams.len());
+    for (index, seam) in seams.iter().enumerate() {
+        assert_eq!(index, seam.index);
+        assert_eq!(SeamKind::Remove, seam.kind);
+        assert_eq!(height as usize, seam.points.len());
+        assert!(seam.points.iter().enumerate().all(|(y, &(_, py))| y == py));
+    }
+
+    let seams = carver.find_seams(Direction::Horizontal, 2);
+    assert_eq!(2, seams.len());
+    for seam in &seams {
+        assert_eq!(Direction::Horizontal, seam.direction);
+        assert!(seam.points.iter().enumerate().all(|(x, &(px, _))| x == px));
+    }
+
+    let mut resized = carver.clone();
+    resized.resize(width as usize - 1, height as usize);
+    let mut removed = resized.get_removed_points().to_vec();
+    removed.sort_by_key(|&(_, y)| y);
+    assert_eq!(carver.find_seams(Direction::Vertical, 1)[0].points, removed);
+}
+
+#[test]
+fn carver_seams_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    for seams_per_pass in [1, 4] {
+        let mut carver = Carver::new(&input).with_seams_per_pass(seams_per_pass);
+        carver.resize(width as usize - 6, height as usize + 3);
+
+        let seams = carver.get_seams();
+        assert_eq!(9, seams.len());
+        let (removed, inserted) = seams.split_at(6);
+        assert!(removed
+            .iter()
+            .all(|seam| seam.kind == SeamKind::Remove && seam.direction == Direction::Vertical));
+        assert!(inserted
+            .iter()
+            .all(|seam| seam.kind == SeamKind::Insert && seam.direction == Direction::Horizontal));
+
+        // Each seam's points are in the image as it was just before it.
+        for (i, seam) in removed.iter().enumerate() {
+            assert!(seam.points.iter().all(|&(x, _)| x < width as usize - i));
+        }
+        for (i, seam) in inserted.iter().enumerate() {
+            assert!(seam.points.iter().all(|&(_, y)| y <= height as usize + i));
+        }
+    }
+}
+
+#[test]
+fn carver_carve_map_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    for (dw, dh) in [(-5, 0), (0, 5), (-5, -5), (5, 5)] {
+        let target = ((width as isize + dw) as u32, (height as isize + dh) as u32);
+        let mut carver = Carver::new(&input);
+        let output = carver.resize(target.0 as usize, target.1 as usize);
+        let map = carver.get_carve_map();
+
+        for (x, y, pixel) in output.pixels() {
+            let (sx, sy) = map.inverse(x as usize, y as usize);
+            if dw <= 0 && dh <= 0 {
+                assert_eq!(input.get_pixel(sx as u32, sy as u32), pixel);
+            }
+            assert!(map.forward(sx, sy).is_some());
+        }
+        for (x, y, _) in input.pixels() {
+            let (ox, oy) = map.forward(x as usize, y as usize).unwrap();
+            assert!(ox < target.0 as usize && oy < target.1 as usize);
+            if dw >= 0 && dh >= 0 {
+                assert_eq!((x as usize, y as usize), map.inverse(ox, oy));
+            }
+        }
+        assert_eq!(None, map.forward(width as usize, 0));
+    }
+}
+
+#[test]
+fn carver_carve_map_rect_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let mut carver = Carver::new(&input).with_hybrid(Hybrid::default());
+    carver.resize(width as usize - 20, height as usize);
+    let map = carver.get_carve_map();
+
+    let whole = Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    };
+    let output = Rect {
+        width: width - 20,
+        ..whole
+    };
+    assert_eq!(Some(output), map.forward_rect(&whole));
+    assert_eq!(Some(whole), map.inverse_rect(&output));
+}
+
+#[test]
+fn carver_replay_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    for (seams_per_pass, dw, dh) in [(1, -5, 4), (4, 6, -3), (1, 3, 3)] {
+        let mut carver = Carver::new(&input).with_seams_per_pass(seams_per_pass);
+        let target = (
+            (width as isize + dw) as usize,
+            (height as isize + dh) as usize,
+        );
+        let output = carver.resize(target.0, target.1);
+
+        let replayed = Carver::replay(carver.get_seams(), &input).unwrap();
+        compare_images(&output, &replayed).expect("replay should match the carve");
+    }
+}
+
+#[test]
+fn carver_replay_mismatched_image_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let mut carver = Carver::new(&input);
+    carver.resize(width as usize - 2, height as usize);
+
+    let small = DynamicImage::new_rgba8(width / 2, height / 2);
+    assert!(Carver::replay(carver.get_seams(), &small).is_err());
+}
+
+#[test]
+fn seams_round_trip_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let mut carver = Carver::new(&input);
+    carver.resize(width as usize - 2, height as usize + 2);
+    let seams = carver.get_seams();
+
+    let mut formats = vec![SeamFormat::Csv];
+    if cfg!(feature = "serde") {
+        formats.push(SeamFormat::Json);
+    }
+    for format in formats {
+        let mut bytes = vec![];
+        write_seams(seams, &mut bytes, format).unwrap();
+        assert_eq!(seams, read_seams(bytes.as_slice(), format).unwrap());
+    }
+}
+
+#[test]
+fn carver_layer_test() {
+    let input = load(INPUT);
+    let (width, height) = input.dimensions();
+
+    let layer = Layer::from_image("copy", &input);
+    let mut carver = Carver::new(&input).with_layer(layer).unwrap();
+    let output = carver.resize(width as usize - 5, height as usize - 5);
+
+    let layers = carver.get_layers();
+    assert_eq!(1, layers.len());
+    assert_eq!("copy", layers[0].name);
+    compare_images(&output, &layers[0].to_image().to_rgba8().into())
+        .expect("the layer should be carved like the image");
+
+    let layer = Layer::from_image("copy", &input).with_interpolation(Interpolation::Linear);
+    let mut carver = Carver::new(&input).with_layer(layer).unwrap();
+    let output = carver.resize(width as usize + 5, height as usize);
+    let layer = &carver.get_layers()[0];
+    for (x, y, pixel) in output.pixels() {
+        let value = layer.get(x as usize, y as usize);
+        for (channel, value) in pixel.0.iter().zip(value) {
+            assert!((*channel as f32 - value * 255.0).abs() <= 1.0);
+        }
+    }
+}
+
+#[test]
+fn carver_saliency_test() {
+    // A flat patch in a field of stripes is the cheapest place for gradient
+    // seams, but also the part of the image that stands out.
+    let patch = |x: u32, y: u32| (16..32).contains(&x) && (16..32).contains(&y);
+    let image = RgbImage::from_fn(48, 48, |x, y| match patch(x, y) {
+        true => image::Rgb([100; 3]),
+        false => image::Rgb([100 + 20 * (x % 3) as u8; 3]),
+    });
+    let input = DynamicImage::ImageRgb8(image);
+
+    let crosses_patch = |saliency_weight| {
+        let mut carver = Carver::new(&input).with_energy_options(EnergyOptions {
+            saliency_weight,
+            ..EnergyOptions::default()
+        });
+        carver.resize(40, 48);
+        carver
+            .get_removed_points()
+            .iter()
+            .any(|&(x, y)| patch(x as u32, y as u32))
+    };
+
+    assert!(crosses_patch(0.0));
+    assert!(!crosses_patch(1.0));
+}
+
+#[test]
+fn carver_layer_energy_test() {
+    // A flat image with a step in its layer between the second and third
+    // columns, which the cheapest seams would otherwise run through.
+    let input = DynamicImage::new_rgba8(40, 20);
+    let data = (0..20)
+        .flat_map(|_| (0..40).map(|x| if x < 2 { 0.0 } else { 1.0 }))
+        .collect();
+    let layer = Layer::new("depth", (40, 20), 1, data).unwrap();
+
+    let crosses_step = |carver: &Carver| {
+        carver
+            .get_removed_points()
+            .iter()
+            .any(|&(x, _)| x == 1 || x == 2)
+    };
+
+    let mut carver = Carver::new(&input).with_layer(layer.clone()).unwrap();
+    carver.resize(30, 20);
+    assert!(crosses_step(&carver));
+
+    let layer = layer.with_energy_weight(1.0);
+    let mut carver = Carver::new(&input).with_layer(layer).unwrap();
+    carver.resize(30, 20);
+    assert!(!crosses_step(&carver));
+}
+
+#[test]
+fn carver_layer_size_test() {
+    let input = load(INPUT);
+    let layer = Layer::new("small", (2, 2), 1, vec![0.0; 4]).unwrap();
+    assert!(Carver::new(&input).with_layer(layer).is_err());
+    assert!(Layer::new("short", (2, 2), 1, vec![0.0; 3]).is_err());
+}
+
+#[test]
+fn temporal_carver_test() {
+    // A flat scene with different sensor noise in each frame, where seams
+    // carved independently land anywhere.
+    let frame = |seed: u32| {
+        let image = RgbImage::from_fn(40, 20, |x, y| {
+            let noise = (x * 31 + y * 17 + seed * 7).wrapping_mul(2_654_435_761) >> 29;
+            image::Rgb([120 + noise as u8; 3])
+        });
+        DynamicImage::ImageRgb8(image)
+    };
+    let (first, second) = (frame(1), frame(2));
+    let target = (30, 20);
+
+    let shared = |coherence: i32| {
+        let mut temporal = TemporalCarver::new(coherence, 8);
+        let removed = [&first, &second].map(|frame| {
+            let mut carver = temporal.guide(Carver::new(frame));
+            carver.resize(target.0, target.1);
+            temporal.follow(&carver);
+            carver.get_removed_points().to_vec()
+        });
+        let [previous, next] = removed;
+        next.iter().filter(|point| previous.contains(point)).count()
+    };
+    let independent = shared(0);
+    let guided = shared(2_000);
+    assert!(
+        guided > independent * 2,
+        "{guided} shared points with guidance, {independent} without"
+    );
+}
+
+#[test]
+fn animation_carver_test() {
+    // A striped scene with three flat corridors, the first two of which a
+    // bright square passes through in later frames. Carving by the first
+    // frame's energy alone would cut through it.
+    let frames: Vec<_> = (0..3)
+        .map(|i| {
+            let image = RgbImage::from_fn(50, 20, |x, y| {
+                let square = 10 * i + 10;
+                let inside = (square..square + 4).contains(&x) && (8..12).contains(&y);
+                let value = match x {
+                    _ if inside => 250,
+                    20..=23 | 30..=33 | 40..=43 => 40,
+                    _ => 180 * ((x / 2) % 2) as u8,
+                };
+                image::Rgb([value; 3])
+            });
+            DynamicImage::ImageRgb8(image)
+        })
+        .collect();
+    let mut carver = make_animation_carver(&frames, |frame| Ok(Carver::new(frame))).unwrap();
+    carver.resize(46, 20);
+
+    let carved: Vec<_> = frames
+        .iter()
+        .map(|frame| Carver::replay(carver.get_seams(), frame).unwrap())
+        .collect();
+    for frame in &carved {
+        assert_eq!((46, 20), frame.dimensions());
+        let bright = frame.pixels().filter(|(_, _, pixel)| pixel.0[0] > 200);
+        assert_eq!(16, bright.count());
+    }
+
+    let delay = Delay::from_numer_denom_ms(100, 1);
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("animation.gif");
+    let output = carved
+        .iter()
+        .map(|frame| Frame::from_parts(frame.to_rgba8(), 0, 0, delay))
+        .collect();
+    write_animation(output, &path, ImageFormat::Gif).unwrap();
+    let Ok((Input::Animation(decoded), Some(ImageFormat::Gif))) = read_input(&path, None) else {
+        panic!("expected an animated GIF");
+    };
+    assert_eq!(3, decoded.len());
+    assert!(decoded.iter().all(|frame| frame.delay() == delay));
+    assert_eq!((46, 20), decoded[0].buffer().dimensions());
+}
+
+#[test]
+fn compact_carver_test() {
+    let input = load(INPUT);
+    let (width, height) = (input.width() as usize, input.height() as usize);
+
+    // Energy is only stale after the first seam, so single seams match.
+    for (target_width, target_height) in [
+        (width - 1, height),
+        (width, height - 1),
+        (width + 1, height),
+    ] {
+        let expected = Carver::new(&input).resize(target_width, target_height);
+        let output = CompactCarver::new(&input).resize(target_width, target_height);
+        assert!(compare_images(&expected, &output).is_ok());
+    }
+
+    let output = CompactCarver::new(&input).resize(width - 20, height + 10);
+    assert_eq!((width as u32 - 20, height as u32 + 10), output.dimensions());
+
+    let target = (width - 20, height + 10);
+    let compact = CompactCarver::estimate_memory((width, height), target);
+    assert!(compact * 4 < Carver::estimate_memory((width, height), target));
+}
+
+#[test]
+fn carver_pyramid_search_test() {
+    let input = load(INPUT);
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let target = (width - 30, height - 10);
+
+    let pyramid = SearchMode::Pyramid { levels: 2, band: 4 };
+    let mut carver = Carver::new(&input).with_search_mode(pyramid);
+    let output = carver.resize(target.0, target.1);
+    assert_eq!((target.0 as u32, target.1 as u32), output.dimensions());
+    let replayed = Carver::replay(carver.get_seams(), &input).unwrap();
+    assert!(compare_images(&output, &replayed).is_ok());
+
+    let full_cost = Carver::new(&input).estimate_cost(target.0, target.1);
+    let pyramid_cost = Carver::new(&input)
+        .with_search_mode(pyramid)
+        .estimate_cost(target.0, target.1);
+    assert!(pyramid_cost < full_cost * 3 / 2);
+}
+
+#[test]
+fn carver_pyramid_narrow_grid_test() {
+    let image = RgbImage::from_fn(100, 12, |x, y| {
+        image::Rgb([((x * 37 + y * 11) % 256) as u8; 3])
+    });
+    let input = DynamicImage::ImageRgb8(image);
+
+    for (width, levels) in [(10, 3), (40, 3), (100, 10), (100, 64), (100, usize::MAX)] {
+        for target in [1, 2, width / 2] {
+            let pyramid = SearchMode::Pyramid { levels, band: 2 };
+            let cropped = input.crop_imm(0, 0, width as u32, 12);
+            let output = Carver::new(&cropped)
+                .with_search_mode(pyramid)
+                .resize(target, 12);
+            assert_eq!((target as u32, 12), output.dimensions());
+        }
+    }
+}
+
+#[test]
+fn carver_progress_test() {
+    let input = load(INPUT);
+    let (width, height) = (input.width() as usize, input.height() as usize);
+
+    let calls = Rc::new(RefCell::new(vec![]));
+    let observed = calls.clone();
+    let mut carver = Carver::new(&input)
+        .with_observer(move |done: usize, total: usize| observed.borrow_mut().push((done, total)));
+    carver.resize(width - 20, height + 10);
+
+    let calls = calls.borrow();
+    assert_eq!(30, calls.len());
+    assert!(calls
+        .iter()
+        .enumerate()
+        .all(|(i, &call)| call == (i + 1, 30)));
+}
+
+#[test]
+fn carver_cancellation_test() {
+    let input = load(INPUT);
+    let (width, height) = (input.width() as usize, input.height() as usize);
+
+    let token = Arc::new(AtomicBool::new(false));
+    let cancel = token.clone();
+    let mut carver =
+        Carver::new(&input)
+            .with_cancellation(token)
+            .with_observer(move |done: usize, _| {
+                if done == 5 {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            });
+    let error = carver.try_resize(width - 20, height - 20).unwrap_err();
+    assert!(error.is::<Cancelled>());
+    assert_eq!(5, carver.get_seams().len());
+    // Probing what a resize would do isn't the resize that was cancelled.
+    assert_eq!(3, carver.find_seams(Direction::Vertical, 3).len());
+    assert!(carver.estimate_cost(width - 30, height) > 0);
+    assert!(carver.get_safe_shrink(Direction::Vertical) > 0);
+
+    let mut carver = Carver::new(&input).with_cancellation(Arc::new(AtomicBool::new(false)));
+    let output = carver.try_resize(width - 20, height - 20).unwrap();
+    assert_eq!((width as u32 - 20, height as u32 - 20), output.dimensions());
+}
+
+#[test]
+fn carver_seams_iterator_test() {
+    let input = load(INPUT);
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let expected = Carver::new(&input).find_seams(Direction::Horizontal, 5);
+
+    let mut carver = Carver::new(&input);
+    let mut seams = carver.seams(Direction::Horizontal);
+    let mut removed = vec![];
+    for (i, seam) in seams.by_ref().take(5).enumerate() {
+        assert_eq!(expected[i], seam);
+        removed.push(seam);
+    }
+    assert_eq!((width, height - 5), seams.size());
+    let replayed = Carver::replay(&removed, &input).unwrap();
+    assert!(compare_images(&replayed, &seams.image()).is_ok());
+    drop(seams);
+
+    assert_eq!((width, height - 5), carver.get_size());
+    let output = carver.resize(width - 3, height - 5);
+    assert_eq!((width as u32 - 3, height as u32 - 5), output.dimensions());
+    assert_eq!(8, carver.get_seams().len());
+}
+
+#[test]
+fn carver_trim_test() {
+    // Stripes either side of a flat band, whose inner eight columns are all
+    // that cost nothing to carve.
+    let image = RgbImage::from_fn(40, 20, |x, _| match x {
+        10..20 => image::Rgb([90, 90, 90]),
+        _ => image::Rgb([120 * (x % 3) as u8; 3]),
+    });
+    let input = DynamicImage::ImageRgb8(image);
+
+    let mut carver = Carver::new(&input);
+    let output = carver.trim(0);
+    assert_eq!((32, 20), output.dimensions());
+    assert_eq!((32, 20), carver.get_size());
+    assert_eq!((8, 0), carver.get_report().carved);
+    assert!(carver.get_seams().iter().all(|seam| seam.cost == 0));
+
+    let mut carver = Carver::new(&input);
+    carver.trim(u32::MAX);
+    assert_eq!((1, 1), carver.get_size());
+}
+
+#[test]
+fn carver_history_test() {
+    let input = load(INPUT);
+    let (width, height) = (input.width() as usize, input.height() as usize);
+
+    let mut carver = Carver::new(&input).with_history();
+    let output = carver.resize(width - 10, height - 5);
+    let seams = carver.get_seams().to_vec();
+
+    assert_eq!(5, carver.undo(5));
+    let replayed = Carver::replay(&seams[..10], &input).unwrap();
+    assert!(compare_images(&replayed, &carver.get_image()).is_ok());
+    assert_eq!(10, carver.undo(20));
+    assert_eq!((width, height), carver.get_size());
+    assert!(compare_images(&input, &carver.get_image()).is_ok());
+    assert!(carver.get_seams().is_empty());
+    assert!(carver.get_removed_points().is_empty());
+
+    assert_eq!(15, carver.redo(20));
+    assert!(compare_images(&output, &carver.get_image()).is_ok());
+    assert_eq!(seams, carver.get_seams());
+
+    // Carving on after undoing matches carving from scratch.
+    carver.undo(15);
+    let output = carver.resize(width - 3, height);
+    let expected = Carver::new(&input).resize(width - 3, height);
+    assert!(compare_images(&expected, &output).is_ok());
+    assert_eq!(0, carver.redo(1));
+
+    let mut carver = Carver::new(&input).with_history().with_seams_per_pass(4);
+    carver.resize(width - 8, height);
+    assert_eq!(8, carver.undo(8));
+    assert!(compare_images(&input, &carver.get_image()).is_ok());
+
+    let mut carver = Carver::new(&input);
+    carver.resize(width - 8, height);
+    assert_eq!(0, carver.undo(8));
+}
+
+#[test]
+fn carver_huge_diagonal_penalty_test() {
+    let input = load(INPUT);
+    let stiffness = Stiffness {
+        max_drift: 1,
+        diagonal_penalty: u32::MAX,
+    };
+    let mut carver = Carver::new(&input).with_stiffness(stiffness);
+    carver.resize(input.width() as usize - 5, input.height() as usize);
+
+    // Any drift costs more than the tallest straight seam could.
+    for seam in carver.get_seams() {
+        let column = seam.points[0].0;
+        assert!(seam.points.iter().all(|&(x, _)| x == column));
+    }
+}
+
 fn load(bytes: &[u8]) -> DynamicImage {
     image::load_from_memory(bytes).expect("loaded test image")
 }
@@ -0,0 +1,224 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use clap::Parser;
+use image::{DynamicImage, Frame, GenericImageView, ImageFormat, Rgb, RgbImage, RgbaImage};
+
+use rmr::codec::{read_image, read_input, write_animation, Input};
+use rmr::config::{Command, Config, OutputOptions};
+
+#[test]
+fn read_image_guessed_format_test() {
+    let mut bytes = Cursor::new(vec![]);
+    let image = DynamicImage::new_rgb8(6, 4);
+    image.write_to(&mut bytes, ImageFormat::Png).unwrap();
+    // Without an extension the format can only come from the bytes.
+    let path = temp_path("guessed");
+    std::fs::write(&path, bytes.get_ref()).unwrap();
+
+    let (decoded, format) = read_image(&path, None).unwrap();
+    assert_eq!(Some(ImageFormat::Png), format);
+    assert_eq!((6, 4), decoded.dimensions());
+
+    std::fs::write(&path, b"not an image").unwrap();
+    assert!(read_image(&path, None).is_err());
+}
+
+#[test]
+fn read_memory_limit_test() {
+    let path = temp_path("limit.png");
+    DynamicImage::new_rgb8(6, 4).save(&path).unwrap();
+    let frame = |shade| Frame::new(RgbaImage::from_pixel(6, 4, image::Rgba([shade; 4])));
+    let animation_path = temp_path("limit.gif");
+    write_animation(
+        vec![frame(0), frame(255)],
+        &animation_path,
+        ImageFormat::Gif,
+    )
+    .unwrap();
+
+    // A small --max-memory never lowers the default limit.
+    for max_memory in [None, Some(1), Some(1 << 40)] {
+        let (image, _) = read_image(&path, max_memory).unwrap();
+        assert_eq!((6, 4), image.dimensions());
+        let (input, _) = read_input(&animation_path, max_memory).unwrap();
+        assert!(matches!(input, Input::Animation(frames) if frames.len() == 2));
+    }
+}
+
+#[test]
+fn parse_format_test() {
+    let format = |args: &[&str]| parse_output_options(args).map(|output| output.format);
+
+    assert_eq!(None, format(&[]).unwrap());
+    for (name, expected) in [
+        ("png", ImageFormat::Png),
+        ("jpg", ImageFormat::Jpeg),
+        ("jpeg", ImageFormat::Jpeg),
+        ("webp", ImageFormat::WebP),
+        ("gif", ImageFormat::Gif),
+    ] {
+        assert_eq!(Some(expected), format(&["--format", name]).unwrap());
+    }
+    assert!(format(&["--format", "bmpx"]).is_err());
+    assert!(format(&["--format", ""]).is_err());
+}
+
+#[test]
+fn output_format_test() {
+    let choose = |format, path: &str, input_format| {
+        let options = OutputOptions {
+            format,
+            ..parse_output_options(&[]).unwrap()
+        };
+        options.get_output_format(Path::new(path), input_format)
+    };
+
+    // An explicit format wins over the extension and the input.
+    let format = choose(Some(ImageFormat::WebP), "out.png", Some(ImageFormat::Gif));
+    assert_eq!(ImageFormat::WebP, format.unwrap());
+    let format = choose(None, "out.png", Some(ImageFormat::Gif));
+    assert_eq!(ImageFormat::Png, format.unwrap());
+
+    // Without an extension, or on stdout, the input's format is reused.
+    let format = choose(None, "out", Some(ImageFormat::Jpeg));
+    assert_eq!(ImageFormat::Jpeg, format.unwrap());
+    let format = choose(None, "-", Some(ImageFormat::Gif));
+    assert_eq!(ImageFormat::Gif, format.unwrap());
+    let format = choose(Some(ImageFormat::Png), "-", None);
+    assert_eq!(ImageFormat::Png, format.unwrap());
+}
+
+#[test]
+fn output_format_missing_test() {
+    let options = parse_output_options(&[]).unwrap();
+    for path in ["-", "out", "out.unknown"] {
+        let error = options
+            .get_output_format(Path::new(path), None)
+            .unwrap_err();
+        assert!(error.to_string().contains("use --format"), "{error}");
+    }
+}
+
+#[test]
+fn jpeg_quality_test() {
+    let input = RgbImage::from_fn(32, 32, |x, y| {
+        let noise = (x * 31 + y * 17).wrapping_mul(2_654_435_761) >> 24;
+        Rgb([noise as u8, (x * 8) as u8, (y * 8) as u8])
+    });
+    let input_path = temp_path("quality.png");
+    input.save(&input_path).unwrap();
+
+    let size = |quality: &str| {
+        let path = temp_path(&format!("quality-{quality}.jpg"));
+        let output = rmr(
+            &[&input_path, &path],
+            &["--width", "30", "--quality", quality],
+        );
+        assert!(output.status.success(), "{output:?}");
+        let (image, format) = read_image(&path, None).unwrap();
+        assert_eq!(Some(ImageFormat::Jpeg), format);
+        assert_eq!((30, 32), image.dimensions());
+        std::fs::metadata(&path).unwrap().len()
+    };
+    assert!(size("10") < size("95"));
+
+    let path = temp_path("quality.webp");
+    let output = rmr(&[&input_path, &path], &["--width", "30", "--quality", "80"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("losslessly"));
+}
+
+#[test]
+fn jpeg_background_test() {
+    // Transparent on the left and opaque blue on the right.
+    let input = RgbaImage::from_fn(16, 8, |x, _| match x {
+        0..8 => image::Rgba([0, 255, 0, 0]),
+        _ => image::Rgba([0, 0, 255, 255]),
+    });
+    let input_path = temp_path("background.png");
+    input.save(&input_path).unwrap();
+
+    let colors = |args: &[&str]| {
+        let path = temp_path("background.jpg");
+        let args = [&["--width", "14", "--quality", "100"], args].concat();
+        let output = rmr(&[&input_path, &path], &args);
+        assert!(output.status.success(), "{output:?}");
+        let (image, _) = read_image(&path, None).unwrap();
+        let [left, right] = [1, 12].map(|x| image.to_rgb8().get_pixel(x, 4).0);
+        (left, right)
+    };
+    let close = |a: [u8; 3], b: [u8; 3]| a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) < 16);
+
+    let (left, right) = colors(&[]);
+    assert!(close([255, 255, 255], left), "{left:?}");
+    assert!(close([0, 0, 255], right), "{right:?}");
+    let (left, right) = colors(&["--background", "#ff0000"]);
+    assert!(close([255, 0, 0], left), "{left:?}");
+    assert!(close([0, 0, 255], right), "{right:?}");
+}
+
+#[test]
+fn animation_output_path_test() {
+    let path = |args: &[&str], suffix| {
+        let args = ["rmr", "resize"].iter().chain(args);
+        match Config::try_parse_from(args).unwrap().command {
+            Command::Resize(config) => config.get_animation_output_path(suffix),
+            _ => unreachable!(),
+        }
+    };
+
+    // Animated PNG and WebP can only be written back as GIF.
+    assert_eq!(Path::new("clip-resized.gif"), path(&["clip.png"], ""));
+    assert_eq!(Path::new("clip-resized.gif"), path(&["clip.webp"], ""));
+    assert_eq!(
+        Path::new("clip-resized-(4, 4).gif"),
+        path(&["clip.png"], "(4, 4)")
+    );
+    assert_eq!(Path::new("out.png"), path(&["clip.png", "out.png"], ""));
+    assert_eq!(Path::new("-"), path(&["-"], ""));
+}
+
+#[test]
+fn animation_output_format_test() {
+    let frame = |shade| Frame::new(RgbaImage::from_pixel(12, 8, image::Rgba([shade; 4])));
+    let input_path = temp_path("format.gif");
+    write_animation(vec![frame(0), frame(255)], &input_path, ImageFormat::Gif).unwrap();
+
+    let path = temp_path("format-resized.png");
+    let _ = std::fs::remove_file(&path);
+    let output = rmr(&[&input_path, &path], &["--width", "10"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--format gif"));
+    assert!(!path.exists());
+
+    let output = rmr(&[&input_path, &path], &["--width", "10", "--format", "gif"]);
+    assert!(output.status.success(), "{output:?}");
+    let (Input::Animation(frames), Some(ImageFormat::Gif)) = read_input(&path, None).unwrap()
+    else {
+        panic!("expected an animated GIF");
+    };
+    assert_eq!((10, 8), frames[0].buffer().dimensions());
+}
+
+fn rmr(paths: &[&Path], args: &[&str]) -> Output {
+    std::process::Command::new(env!("CARGO_BIN_EXE_rmr"))
+        .arg("resize")
+        .args(paths)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+fn parse_output_options(args: &[&str]) -> Result<OutputOptions, clap::Error> {
+    let args = ["rmr", "resize", "-", "-"].iter().chain(args);
+    match Config::try_parse_from(args)?.command {
+        Command::Resize(config) => Ok(config.output),
+        _ => unreachable!(),
+    }
+}
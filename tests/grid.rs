@@ -245,6 +245,25 @@ fn grid_iter_parents_test() {
     );
 }
 
+#[test]
+fn grid_iter_parents_within_test() {
+    let grid = make_test_grid();
+
+    assert!(grid.iter_parents_within(1, 0, 2).next().is_none());
+
+    // A drift of zero only reaches the point directly above.
+    assert_eq!(
+        vec![((1, 1), &4)],
+        grid.iter_parents_within(1, 2, 0).collect::<Vec<_>>()
+    );
+
+    // A wide drift is clamped to the grid.
+    assert_eq!(
+        vec![((0, 0), &0), ((1, 0), &1), ((2, 0), &2)],
+        grid.iter_parents_within(0, 1, 2).collect::<Vec<_>>()
+    );
+}
+
 #[test]
 fn grid_get_parents_indexed_test() {
     let grid = make_test_grid();
@@ -283,6 +302,23 @@ fn grid_get_parents_indexed_test() {
     );
 }
 
+#[test]
+fn grid_iter_window_test() {
+    let grid = make_test_grid();
+
+    assert_eq!(vec![&4], grid.iter_window(1, 1, 0).collect::<Vec<_>>());
+    assert_eq!(
+        vec![&0, &1, &2, &3, &4, &5, &6, &7, &8],
+        grid.iter_window(1, 1, 1).collect::<Vec<_>>()
+    );
+
+    // Windows are clamped rather than wrapped at the edges.
+    assert_eq!(
+        vec![((1, 1), &4), ((2, 1), &5), ((1, 2), &7), ((2, 2), &8)],
+        grid.iter_window_with_coords(2, 2, 1).collect::<Vec<_>>()
+    );
+}
+
 #[test]
 fn grid_get_row_test() {
     let grid = make_test_grid();
@@ -414,6 +450,46 @@ fn grid_add_last_column_test() {
     assert_eq!(&8, grid.get(3, 2));
 }
 
+#[test]
+fn grid_insert_point_test() {
+    let mut grid = make_test_grid();
+
+    grid.insert_point(0, 0, 10);
+    grid.insert_point(1, 1, 11);
+    grid.insert_point(3, 2, 12);
+    assert_eq!(4, grid.width());
+    assert_eq!(
+        vec![(0, 0, &10), (1, 0, &0), (2, 0, &1), (3, 0, &2)],
+        grid.get_row_with_coords(0)
+    );
+    assert_eq!(
+        vec![(0, 1, &3), (1, 1, &11), (2, 1, &4), (3, 1, &5)],
+        grid.get_row_with_coords(1)
+    );
+    assert_eq!(
+        vec![(0, 2, &6), (1, 2, &7), (2, 2, &8), (3, 2, &12)],
+        grid.get_row_with_coords(2)
+    );
+}
+
+#[test]
+fn grid_token_insert_point_test() {
+    let mut grid = make_test_grid();
+    let token = grid.make_token(1, 0);
+
+    grid.shift_row_left_from_point(0, 0);
+    grid.shift_row_left_from_point(0, 1);
+    grid.shift_row_left_from_point(0, 2);
+    grid.remove_last_column();
+    assert_eq!(Some((0, 0)), grid.get_token_position(&token));
+
+    for y in 0..grid.height() {
+        grid.insert_point(0, y, 10);
+    }
+    assert_eq!(Some((1, 0)), grid.get_token_position(&token));
+    assert_eq!(&1, grid.trade(token).unwrap());
+}
+
 #[test]
 fn grid_token_trade_test() {
     let mut grid = make_test_grid();
@@ -485,6 +561,51 @@ fn grid_token_shift_row_left_from_point_test() {
     assert!(iter.next().is_none());
 }
 
+#[test]
+fn grid_token_repeated_shift_row_left_from_point_test() {
+    let mut grid = make_test_grid();
+    let token = grid.make_token(2, 0);
+
+    grid.shift_row_left_from_point(1, 0);
+    grid.shift_row_left_from_point(0, 0);
+    grid.remove_last_column();
+    grid.remove_last_column();
+
+    assert_eq!(&2, grid.trade(token).unwrap());
+}
+
+#[test]
+fn grid_token_shift_row_left_from_middle_test() {
+    let mut grid = make_test_grid();
+    let tokens: Vec<_> = (0..3).map(|x| grid.make_token(x, 1)).collect();
+
+    grid.shift_row_left_from_point(1, 1);
+
+    assert_eq!(Some((0, 1)), grid.get_token_position(&tokens[0]));
+    assert_eq!(None, grid.get_token_position(&tokens[1]));
+    assert_eq!(Some((1, 1)), grid.get_token_position(&tokens[2]));
+    // The last position keeps a copy of its neighbour without a position,
+    // so shifting the row again only moves the live points.
+    assert_eq!(vec![&3, &5, &5], grid.get_row(1));
+    grid.shift_row_left_from_point(0, 1);
+    assert_eq!(None, grid.get_token_position(&tokens[0]));
+    assert_eq!(Some((0, 1)), grid.get_token_position(&tokens[2]));
+    assert_eq!(vec![&5, &5, &5], grid.get_row(1));
+}
+
+#[test]
+fn grid_token_shift_row_left_from_last_point_test() {
+    let mut grid = make_test_grid();
+    let tokens: Vec<_> = (0..3).map(|x| grid.make_token(x, 0)).collect();
+
+    grid.shift_row_left_from_point(2, 0);
+
+    assert_eq!(vec![&0, &1, &2], grid.get_row(0));
+    assert_eq!(Some((0, 0)), grid.get_token_position(&tokens[0]));
+    assert_eq!(Some((1, 0)), grid.get_token_position(&tokens[1]));
+    assert_eq!(None, grid.get_token_position(&tokens[2]));
+}
+
 #[test]
 fn grid_token_shift_row_right_from_point_test() {
     let mut grid = make_test_grid();
@@ -665,6 +786,21 @@ fn grid_rotation_shift_row_left_from_point_test() {
     assert_eq!(&8, grid.get(2, 2));
 }
 
+#[test]
+fn grid_rotation_token_shift_row_left_from_point_test() {
+    let mut grid = make_test_grid();
+    grid.rotate();
+    let tokens: Vec<_> = (0..3).map(|x| grid.make_token(x, 1)).collect();
+
+    grid.shift_row_left_from_point(1, 1);
+
+    assert_eq!(&1, grid.trade(tokens[0].clone()).unwrap());
+    assert!(grid.trade(tokens[1].clone()).is_none());
+    assert_eq!(&7, grid.trade(tokens[2].clone()).unwrap());
+    assert_eq!(Some((1, 1)), grid.get_token_position(&tokens[2]));
+    assert_eq!(vec![&1, &7, &7], grid.get_row(1));
+}
+
 #[test]
 fn grid_rotation_shift_row_right_from_point_test() {
     let mut grid = make_test_grid();